@@ -1,7 +1,17 @@
+mod log;
+mod observer;
+mod propagation;
+mod sink;
 mod span;
 mod tag;
 mod tracer;
 
+pub use crate::log::Log;
+pub use crate::observer::SpanObserver;
+pub use crate::propagation::{
+    ExtractFormat, Format, InjectFormat, PropagationError, TextMapReader, TextMapWriter,
+};
+pub use crate::sink::SpanSink;
 pub use crate::span::{BaggageItem, Span, SpanBuilder, SpanContext, SpanReference};
 pub use crate::tag::{Tag, TagValue};
 pub use crate::tracer::Tracer;
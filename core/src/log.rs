@@ -0,0 +1,28 @@
+use std::time::SystemTime;
+
+use crate::Tag;
+
+/// A timestamped set of key/value fields recorded on a span.
+#[derive(Clone, Debug)]
+pub struct Log {
+    timestamp: SystemTime,
+    fields: Vec<Tag>,
+}
+
+impl Log {
+    pub fn new(timestamp: SystemTime, fields: Vec<Tag>) -> Self {
+        Self { timestamp, fields }
+    }
+
+    pub fn timestamp(&self) -> SystemTime {
+        self.timestamp
+    }
+
+    pub fn fields(&self) -> &[Tag] {
+        &self.fields
+    }
+
+    pub fn split(self) -> (SystemTime, Vec<Tag>) {
+        (self.timestamp, self.fields)
+    }
+}
@@ -0,0 +1,16 @@
+use crate::{Span, Tag};
+
+/// Hooks a tracer can attach to its spans to react to changes made through
+/// the `Span` API.
+///
+/// Tags the hooks set on `span` do not notify the observer again.
+pub trait SpanObserver<S>: Send + Sync
+where
+    S: 'static + Send + Sync,
+{
+    /// Called after `tag` has been set on `span`.
+    fn on_set_tag(&self, span: &mut Span<S>, tag: &Tag);
+
+    /// Called when `span` finishes, before it is reported.
+    fn on_finish(&self, span: &mut Span<S>);
+}
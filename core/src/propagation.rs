@@ -0,0 +1,139 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Carrier formats defined by the OpenTracing specification.
+///
+/// `TextMap` and `HttpHeaders` carry a `SpanContext` as string key/value
+/// pairs, the latter with keys that are safe to use as HTTP headers.
+/// `Binary` carries it as an opaque byte stream.
+pub enum Format<T, B> {
+    TextMap(T),
+    HttpHeaders(T),
+    Binary(B),
+}
+
+/// Carrier passed to `Tracer::inject`.
+pub type InjectFormat<'a> = Format<&'a mut dyn TextMapWriter, &'a mut dyn io::Write>;
+
+/// Carrier passed to `Tracer::extract`.
+pub type ExtractFormat<'a> = Format<&'a dyn TextMapReader, &'a mut dyn io::Read>;
+
+pub trait TextMapWriter {
+    fn set(&mut self, key: &str, value: &str);
+}
+
+pub trait TextMapReader {
+    fn get(&self, key: &str) -> Option<&str>;
+    fn keys(&self) -> Vec<&str>;
+}
+
+impl TextMapWriter for HashMap<String, String> {
+    fn set(&mut self, key: &str, value: &str) {
+        self.insert(key.to_owned(), value.to_owned());
+    }
+}
+
+impl TextMapReader for HashMap<String, String> {
+    fn get(&self, key: &str) -> Option<&str> {
+        HashMap::get(self, key).map(String::as_str)
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        HashMap::keys(self).map(String::as_str).collect()
+    }
+}
+
+impl TextMapWriter for BTreeMap<String, String> {
+    fn set(&mut self, key: &str, value: &str) {
+        self.insert(key.to_owned(), value.to_owned());
+    }
+}
+
+impl TextMapReader for BTreeMap<String, String> {
+    fn get(&self, key: &str) -> Option<&str> {
+        BTreeMap::get(self, key).map(String::as_str)
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        BTreeMap::keys(self).map(String::as_str).collect()
+    }
+}
+
+#[derive(Debug)]
+pub enum PropagationError {
+    /// The tracer does not support the requested format.
+    UnsupportedFormat,
+    /// The carrier holds a span context that could not be parsed.
+    SpanContextCorrupted(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for PropagationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PropagationError::UnsupportedFormat => write!(f, "unsupported carrier format"),
+            PropagationError::SpanContextCorrupted(reason) => {
+                write!(f, "span context corrupted: {}", reason)
+            }
+            PropagationError::Io(err) => write!(f, "carrier I/O error: {}", err),
+        }
+    }
+}
+
+impl Error for PropagationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PropagationError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PropagationError {
+    fn from(err: io::Error) -> Self {
+        PropagationError::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<C>(mut carrier: C)
+    where
+        C: TextMapReader + TextMapWriter,
+    {
+        carrier.set("trace", "1");
+        carrier.set("baggage", "a");
+        carrier.set("baggage", "b");
+
+        assert_eq!(carrier.get("trace"), Some("1"));
+        assert_eq!(carrier.get("baggage"), Some("b"));
+        assert_eq!(carrier.get("missing"), None);
+
+        let mut keys = carrier.keys();
+        keys.sort_unstable();
+        assert_eq!(keys, ["baggage", "trace"]);
+    }
+
+    #[test]
+    fn hash_maps_are_carriers() {
+        round_trip(HashMap::new());
+    }
+
+    #[test]
+    fn btree_maps_are_carriers() {
+        round_trip(BTreeMap::new());
+    }
+
+    #[test]
+    fn wraps_io_errors() {
+        let err = PropagationError::from(io::Error::new(io::ErrorKind::UnexpectedEof, "eof"));
+
+        assert!(matches!(err, PropagationError::Io(_)));
+        assert!(err.source().is_some());
+        assert_eq!(err.to_string(), "carrier I/O error: eof");
+    }
+}
@@ -0,0 +1,16 @@
+use crate::Span;
+
+/// Receives finished spans.
+///
+/// This is where spans leave the core crate, which depends on no async
+/// runtime: tracers report them from their sink however they see fit.
+///
+/// Spans are submitted from whichever thread finishes or drops them, so
+/// `submit` must neither block nor panic, including once the receiving end is
+/// gone.
+pub trait SpanSink<S>: Send + Sync
+where
+    S: 'static + Send + Sync,
+{
+    fn submit(&self, span: Span<S>);
+}
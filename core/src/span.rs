@@ -1,9 +1,9 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
-use futures::sync::mpsc;
-use futures::{Future, Sink};
-
-use crate::Tag;
+use crate::{Log, SpanObserver, SpanSink, Tag};
 
 #[derive(Debug)]
 pub enum SpanReference<S> {
@@ -24,7 +24,7 @@ where
     S: 'static + Send + Sync,
 {
     pub fn new<O>(
-        sender: mpsc::UnboundedSender<Span<S>>,
+        sink: Arc<dyn SpanSink<S>>,
         operation_name: O,
         start_time: SystemTime,
         tags: Vec<Tag>,
@@ -39,18 +39,28 @@ where
         let operation_name = operation_name.into();
         let finish_time = None;
         let inner = Some(Inner {
-            sender,
+            sink,
             operation_name,
             start_time,
             finish_time,
             tags,
+            logs: Vec::new(),
             references,
             context,
+            observer: None,
         });
 
         Self { inner }
     }
 
+    /// Notifies `observer` of the changes made to this span.
+    pub fn with_observer(mut self, observer: Arc<dyn SpanObserver<S>>) -> Self {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.observer = Some(observer);
+        }
+        self
+    }
+
     pub fn context(&self) -> &SpanContext<S> {
         &self.inner.as_ref().unwrap().context
     }
@@ -59,19 +69,41 @@ where
         self.inner.as_ref().unwrap().start_time
     }
 
+    /// Time between start and finish, zero if the span finished before it
+    /// started, e.g. with a start time in the future or a clock step back.
     pub fn duration(&self) -> Duration {
         let inner = self.inner.as_ref().unwrap();
         inner
             .finish_time
             .unwrap()
             .duration_since(inner.start_time)
-            .unwrap()
+            .unwrap_or_default()
+    }
+
+    pub fn baggage_item(&self, key: &str) -> Option<&str> {
+        self.context().baggage_item(key)
+    }
+
+    /// Sets a baggage item that propagates to every descendant of this span,
+    /// replacing any previous value for `key`.
+    pub fn set_baggage_item<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.context.set_baggage_item(key, value);
+        }
     }
 
     pub fn tags(&self) -> &[Tag] {
         &self.inner.as_ref().unwrap().tags
     }
 
+    pub fn references(&self) -> &[SpanReference<S>] {
+        &self.inner.as_ref().unwrap().references
+    }
+
     pub fn set_operation_name<O>(&mut self, op_name: O)
     where
         O: Into<String>,
@@ -86,30 +118,65 @@ where
     }
 
     pub fn set_tag(&mut self, tag: Tag) {
+        let inner = match self.inner.as_mut() {
+            Some(inner) => inner,
+            None => return,
+        };
+        let observer = match inner.observer.take() {
+            Some(observer) => observer,
+            None => {
+                inner.tags.push(tag);
+                return;
+            }
+        };
+
+        inner.tags.push(tag.clone());
+        observer.on_set_tag(self, &tag);
+        self.restore_observer(observer);
+    }
+
+    /// Records `fields` as an event that happened now.
+    pub fn log_kv(&mut self, fields: Vec<Tag>) {
+        self.log_at(SystemTime::now(), fields);
+    }
+
+    /// Records `fields` as an event that happened at `time`.
+    pub fn log_at(&mut self, time: SystemTime, fields: Vec<Tag>) {
         if let Some(inner) = self.inner.as_mut() {
-            inner.tags.push(tag);
+            inner.logs.push(Log::new(time, fields));
         }
     }
 
+    pub fn events(&self) -> &[Log] {
+        &self.inner.as_ref().unwrap().logs
+    }
+
     pub fn is_finished(&self) -> bool {
         self.inner.as_ref().unwrap().finish_time.is_some()
     }
 
+    /// Records the finish time and submits the span to its sink, without
+    /// blocking. Safe to call from any thread, inside an executor or not.
     pub fn finish(&mut self) {
         if self.inner.is_none() || self.is_finished() {
             return;
         }
+        if let Some(observer) = self.inner.as_mut().unwrap().observer.take() {
+            observer.on_finish(self);
+        }
+
         let inner = self.inner.as_mut().unwrap();
         inner.finish_time = Some(SystemTime::now());
-        let sender = inner.sender.clone();
-        tokio::spawn(
-            sender
-                .send(Span {
-                    inner: self.inner.take(),
-                })
-                .map(|_| ())
-                .map_err(|_| ()),
-        );
+        let sink = inner.sink.clone();
+        sink.submit(Span {
+            inner: self.inner.take(),
+        });
+    }
+
+    fn restore_observer(&mut self, observer: Arc<dyn SpanObserver<S>>) {
+        if let Some(inner) = self.inner.as_mut() {
+            inner.observer = Some(observer);
+        }
     }
 }
 
@@ -122,40 +189,75 @@ where
     }
 }
 
-#[derive(Debug)]
 struct Inner<S>
 where
     S: 'static + Send + Sync,
 {
-    sender: mpsc::UnboundedSender<Span<S>>,
+    sink: Arc<dyn SpanSink<S>>,
     operation_name: String,
     start_time: SystemTime,
     finish_time: Option<SystemTime>,
     tags: Vec<Tag>,
+    logs: Vec<Log>,
     references: Vec<SpanReference<S>>,
     context: SpanContext<S>,
+    observer: Option<Arc<dyn SpanObserver<S>>>,
 }
 
-#[derive(Debug)]
+impl<S> fmt::Debug for Inner<S>
+where
+    S: 'static + Send + Sync + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Inner")
+            .field("operation_name", &self.operation_name)
+            .field("start_time", &self.start_time)
+            .field("finish_time", &self.finish_time)
+            .field("tags", &self.tags)
+            .field("logs", &self.logs)
+            .field("references", &self.references)
+            .field("context", &self.context)
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct SpanContext<S> {
     state: S,
-    baggage_items: Vec<BaggageItem>,
+    baggage: BTreeMap<String, String>,
 }
 
 impl<S> SpanContext<S> {
+    /// Creates a span context, later baggage items replace earlier ones with
+    /// the same key.
     pub fn new(state: S, baggage_items: Vec<BaggageItem>) -> Self {
-        Self {
-            state,
-            baggage_items,
-        }
+        let baggage = baggage_items
+            .into_iter()
+            .map(|item| (item.key, item.value))
+            .collect();
+
+        Self { state, baggage }
     }
 
     pub fn state(&self) -> &S {
         &self.state
     }
 
-    pub fn baggage_items(&self) -> &Vec<BaggageItem> {
-        &self.baggage_items
+    pub fn baggage_item(&self, key: &str) -> Option<&str> {
+        self.baggage.get(key).map(String::as_str)
+    }
+
+    pub fn set_baggage_item<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.baggage.insert(key.into(), value.into());
+    }
+
+    /// Iterates the baggage items as `(key, value)` pairs, ordered by key.
+    pub fn baggage_items(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.baggage.iter().map(|(k, v)| (k.as_str(), v.as_str()))
     }
 }
 
@@ -172,14 +274,126 @@ impl BaggageItem {
             value: value.to_owned(),
         }
     }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
 }
 
-pub trait SpanBuilder<S>
+pub trait SpanBuilder<S>: Sized
 where
     S: Send + Sync,
 {
     fn start(self) -> Span<S>;
-    fn child_of(self, parent: &Span<S>) -> Self;
     fn start_time(self, time: SystemTime) -> Self;
     fn tag(self, tag: Tag) -> Self;
+
+    /// Adds a reference to another span context. The new span inherits the
+    /// baggage of every context it references.
+    fn add_reference(self, reference: SpanReference<&SpanContext<S>>) -> Self;
+
+    fn child_of(self, parent: &SpanContext<S>) -> Self {
+        self.add_reference(SpanReference::ChildOf(parent))
+    }
+
+    fn follows_from(self, context: &SpanContext<S>) -> Self {
+        self.add_reference(SpanReference::FollowsFrom(context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    /// Keeps the spans submitted to it.
+    #[derive(Default)]
+    struct CollectingSink {
+        spans: Mutex<Vec<Span<()>>>,
+    }
+
+    impl SpanSink<()> for CollectingSink {
+        fn submit(&self, span: Span<()>) {
+            self.spans.lock().unwrap().push(span);
+        }
+    }
+
+    fn finished_span(start_time: SystemTime) -> Span<()> {
+        let sink = Arc::new(CollectingSink::default());
+        let mut span = Span::new(
+            sink.clone(),
+            "op",
+            start_time,
+            Vec::new(),
+            Vec::new(),
+            (),
+            Vec::new(),
+        );
+        span.finish();
+
+        let span = sink.spans.lock().unwrap().pop().unwrap();
+        span
+    }
+
+    #[test]
+    fn measures_duration() {
+        let span = finished_span(SystemTime::now() - Duration::from_secs(2));
+        assert!(span.duration() >= Duration::from_secs(2));
+    }
+
+    #[test]
+    fn clamps_duration_of_spans_starting_in_the_future() {
+        let span = finished_span(SystemTime::now() + Duration::from_secs(60));
+        assert_eq!(span.duration(), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn overwrites_baggage_items() {
+        let mut context = SpanContext::new((), Vec::new());
+        context.set_baggage_item("tenant", "acme");
+        context.set_baggage_item("tenant", "globex");
+
+        assert_eq!(context.baggage_item("tenant"), Some("globex"));
+        assert_eq!(
+            context.baggage_items().collect::<Vec<_>>(),
+            [("tenant", "globex")]
+        );
+    }
+
+    #[test]
+    fn later_baggage_items_win_on_creation() {
+        let context = SpanContext::new(
+            (),
+            vec![
+                BaggageItem::new("tenant", "acme"),
+                BaggageItem::new("tenant", "globex"),
+            ],
+        );
+
+        assert_eq!(context.baggage_item("tenant"), Some("globex"));
+    }
+
+    #[test]
+    fn child_baggage_is_independent_of_parent() {
+        let mut parent = SpanContext::new((), Vec::new());
+        parent.set_baggage_item("tenant", "acme");
+
+        // Builders copy the baggage of the contexts a span references.
+        let inherited = parent
+            .baggage_items()
+            .map(|(key, value)| BaggageItem::new(key, value))
+            .collect();
+        let mut child = SpanContext::new((), inherited);
+        child.set_baggage_item("tenant", "globex");
+        child.set_baggage_item("user", "alice");
+
+        assert_eq!(parent.baggage_item("tenant"), Some("acme"));
+        assert_eq!(parent.baggage_item("user"), None);
+        assert_eq!(child.baggage_item("tenant"), Some("globex"));
+    }
 }
@@ -29,11 +29,13 @@ impl Tag {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum TagValue {
     String(String),
     Bool(bool),
-    Number(f64),
+    Long(i64),
+    Double(f64),
+    Binary(Vec<u8>),
 }
 
 impl From<&'static str> for TagValue {
@@ -54,14 +56,44 @@ impl From<bool> for TagValue {
     }
 }
 
+impl From<i32> for TagValue {
+    fn from(f: i32) -> Self {
+        TagValue::Long(i64::from(f))
+    }
+}
+
+impl From<u32> for TagValue {
+    fn from(f: u32) -> Self {
+        TagValue::Long(i64::from(f))
+    }
+}
+
 impl From<i64> for TagValue {
     fn from(f: i64) -> Self {
-        TagValue::Number(f as f64)
+        TagValue::Long(f)
+    }
+}
+
+impl From<f32> for TagValue {
+    fn from(f: f32) -> Self {
+        TagValue::Double(f64::from(f))
     }
 }
 
 impl From<f64> for TagValue {
     fn from(f: f64) -> Self {
-        TagValue::Number(f)
+        TagValue::Double(f)
+    }
+}
+
+impl From<Vec<u8>> for TagValue {
+    fn from(f: Vec<u8>) -> Self {
+        TagValue::Binary(f)
+    }
+}
+
+impl From<&[u8]> for TagValue {
+    fn from(f: &[u8]) -> Self {
+        TagValue::Binary(f.to_vec())
     }
 }
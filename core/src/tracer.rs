@@ -1,3 +1,5 @@
+use crate::{ExtractFormat, InjectFormat, PropagationError, SpanContext};
+
 pub trait Tracer {
     type SpanState;
     type SpanBuilder;
@@ -5,4 +7,18 @@ pub trait Tracer {
     fn span<N>(&mut self, operation_name: N) -> Self::SpanBuilder
     where
         N: Into<String>;
+
+    /// Writes `context` into the carrier so it can cross a process boundary.
+    fn inject(
+        &self,
+        context: &SpanContext<Self::SpanState>,
+        format: InjectFormat<'_>,
+    ) -> Result<(), PropagationError>;
+
+    /// Reads a span context from the carrier, returning `None` if the
+    /// carrier does not hold one.
+    fn extract(
+        &self,
+        format: ExtractFormat<'_>,
+    ) -> Result<Option<SpanContext<Self::SpanState>>, PropagationError>;
 }
@@ -1,75 +1,82 @@
 use std::time::Duration;
 
-use futures::lazy;
+use tokio::time::sleep;
 
 use opentracing_rs::{
     jaeger::{Tracer as JaegerTracer, TransportProtocol},
     SpanBuilder, Tracer,
 };
 
-fn main() {
-    tokio::run(lazy(move || {
-        let mut tracer = JaegerTracer::builder()
-            .const_sampler(true)
-            .udp_remote_reporter(
-                "rust_jaeger_example",
-                "127.0.0.1:6831".parse().unwrap(),
-                TransportProtocol::ThriftCompact,
-                Duration::from_millis(500),
-            )
-            .build_and_serve();
-        {
-            let mut tracer = tracer.clone();
-            tokio::spawn(lazy(move || {
-                let span = tracer.span("hello 1").start();
-                println!("hello 1");
+#[tokio::main]
+async fn main() {
+    let tracer = JaegerTracer::builder()
+        .const_sampler(true)
+        .udp_remote_reporter(
+            "rust_jaeger_example",
+            "127.0.0.1:6831".parse().unwrap(),
+            TransportProtocol::ThriftCompact,
+            Duration::from_millis(500),
+        )
+        .and_then(|builder| builder.build_and_serve())
+        .expect("failed to set up the tracer");
 
-                std::thread::sleep(std::time::Duration::from_secs(1));
+    let hello_1 = {
+        let mut tracer = tracer.clone();
+        tokio::spawn(async move {
+            let span = tracer.span("hello 1").start();
+            println!("hello 1");
 
-                let child_span = tracer.span("testing 1").child_of(&span);
-                {
-                    println!("hello 1 -> testing 1");
-                    let _child_span = child_span.start();
-                    std::thread::sleep(std::time::Duration::from_secs(2));
-                }
+            sleep(Duration::from_secs(1)).await;
 
-                let child_span = tracer.span("testing 2").child_of(&span);
-                {
-                    println!("hello 1 -> testing 2");
-                    let _child_span = child_span.start();
-                    std::thread::sleep(std::time::Duration::from_secs(2));
-                }
+            let child_span = tracer.span("testing 1").child_of(span.context());
+            {
+                println!("hello 1 -> testing 1");
+                let _child_span = child_span.start();
+                sleep(Duration::from_secs(2)).await;
+            }
 
-                std::thread::sleep(std::time::Duration::from_secs(2));
+            let child_span = tracer.span("testing 2").child_of(span.context());
+            {
+                println!("hello 1 -> testing 2");
+                let _child_span = child_span.start();
+                sleep(Duration::from_secs(2)).await;
+            }
 
-                Ok(())
-            }));
-        }
+            sleep(Duration::from_secs(2)).await;
+        })
+    };
 
-        tokio::spawn(lazy(move || {
+    let hello_2 = {
+        let mut tracer = tracer.clone();
+        tokio::spawn(async move {
             let span = tracer.span("hello 2").start();
             println!("hello 2");
 
-            std::thread::sleep(std::time::Duration::from_secs(1));
+            sleep(Duration::from_secs(1)).await;
 
-            let child_span = tracer.span("testing 1").child_of(&span);
+            let child_span = tracer.span("testing 1").child_of(span.context());
             {
                 println!("hello 2 -> testing 2");
                 let _child_span = child_span.start();
-                std::thread::sleep(std::time::Duration::from_secs(2));
+                sleep(Duration::from_secs(2)).await;
             }
 
-            let child_span = tracer.span("testing 2").child_of(&span);
+            let child_span = tracer.span("testing 2").child_of(span.context());
             {
                 println!("hello 2 -> testing 2");
                 let _child_span = child_span.start();
-                std::thread::sleep(std::time::Duration::from_secs(2));
+                sleep(Duration::from_secs(2)).await;
             }
 
-            std::thread::sleep(std::time::Duration::from_secs(2));
-            Ok(())
-        }));
+            sleep(Duration::from_secs(2)).await;
+        })
+    };
+
+    hello_1.await.unwrap();
+    hello_2.await.unwrap();
 
-        Ok(())
-    }));
+    tracer
+        .close(Duration::from_secs(5))
+        .await
+        .expect("failed to report the remaining spans");
 }
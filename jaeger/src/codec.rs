@@ -1,6 +1,11 @@
-use opentracing_rs_core::{Tag, TagValue};
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use crate::{thrift_gen::jaeger, Process, Span, SpanBatch};
+use ordered_float::OrderedFloat;
+
+use opentracing_rs_core::{Log, Tag, TagValue};
+
+use crate::{thrift_gen::jaeger, Process, Span, SpanBatch, SpanReference};
 
 impl From<Tag> for jaeger::Tag {
     fn from(tag: Tag) -> Self {
@@ -10,51 +15,74 @@ impl From<Tag> for jaeger::Tag {
             TagValue::String(v) => {
                 Self::new(name, jaeger::TagType::STRING, v, None, None, None, None)
             }
-            TagValue::Number(v) => Self::new(
+            TagValue::Long(v) => Self::new(name, jaeger::TagType::LONG, None, None, None, v, None),
+            TagValue::Double(v) => Self::new(
                 name,
                 jaeger::TagType::DOUBLE,
                 None,
+                OrderedFloat(v),
                 None,
                 None,
-                v as i64,
                 None,
             ),
+            TagValue::Binary(v) => {
+                Self::new(name, jaeger::TagType::BINARY, None, None, None, None, v)
+            }
         }
     }
 }
 
+impl From<Log> for jaeger::Log {
+    fn from(log: Log) -> Self {
+        let (timestamp, fields) = log.split();
+
+        jaeger::Log::new(
+            to_timestamp(timestamp),
+            fields.into_iter().map(From::from).collect(),
+        )
+    }
+}
+
+impl<'a> From<&'a SpanReference> for jaeger::SpanRef {
+    fn from(reference: &'a SpanReference) -> Self {
+        let (ref_type, state) = match reference {
+            SpanReference::ChildOf(state) => (jaeger::SpanRefType::CHILD_OF, state),
+            SpanReference::FollowsFrom(state) => (jaeger::SpanRefType::FOLLOWS_FROM, state),
+        };
+
+        jaeger::SpanRef::new(
+            ref_type,
+            state.trace_id.low as i64,
+            state.trace_id.high as i64,
+            state.span_id as i64,
+        )
+    }
+}
+
 impl From<Span> for jaeger::Span {
     fn from(span: Span) -> Self {
         let ctx = span.context();
         let state = ctx.state();
 
-        let start_time = {
-            let ts = span
-                .start_time()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap();
-            (ts.as_secs() * 1_000_000 + (ts.subsec_nanos() as u64 / 1000)) as i64
-        };
-
-        let duration = {
-            let duration = span.duration();
+        let start_time = to_timestamp(span.start_time());
+        let duration = to_micros(span.duration());
 
-            (duration.as_secs() * 1_000_000 + (duration.subsec_nanos() as u64 / 1000)) as i64
-        };
+        let tags: Vec<jaeger::Tag> = span.tags().iter().cloned().map(From::from).collect();
+        let logs: Vec<jaeger::Log> = span.events().iter().cloned().map(From::from).collect();
+        let references: Vec<jaeger::SpanRef> = span.references().iter().map(From::from).collect();
 
-        let flags = 0;
         jaeger::Span::new(
             state.trace_id.low as i64,
             state.trace_id.high as i64,
             state.span_id as i64,
             state.parent_span_id.unwrap_or_default() as i64,
             span.operation_name().to_owned(),
-            None,
-            flags,
+            non_empty(references),
+            i32::from(state.flags().bits()),
             start_time,
             duration,
-            None,
-            None,
+            non_empty(tags),
+            non_empty(logs),
             None,
         )
     }
@@ -92,3 +120,204 @@ impl From<SpanBatch> for jaeger::Batch {
         jaeger::Batch::new(From::from(batch.process), spans)
     }
 }
+
+fn to_micros(duration: Duration) -> i64 {
+    i64::try_from(duration.as_micros()).unwrap_or(i64::MAX)
+}
+
+/// Microseconds since the Unix epoch, times before it are sent as the epoch.
+fn to_timestamp(time: SystemTime) -> i64 {
+    to_micros(time.duration_since(UNIX_EPOCH).unwrap_or_default())
+}
+
+/// Optional Thrift lists are left unset rather than sent empty.
+fn non_empty<T>(list: Vec<T>) -> Option<Vec<T>> {
+    if list.is_empty() {
+        None
+    } else {
+        Some(list)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{testing, SpanState, TraceId};
+
+    /// Tag named `key` of `v_type` with every value slot empty.
+    fn empty_tag(v_type: jaeger::TagType) -> jaeger::Tag {
+        jaeger::Tag::new("key".to_owned(), v_type, None, None, None, None, None)
+    }
+
+    #[test]
+    fn converts_string_tags() {
+        assert_eq!(
+            jaeger::Tag::from(Tag::new("key", "value")),
+            jaeger::Tag {
+                v_str: Some("value".to_owned()),
+                ..empty_tag(jaeger::TagType::STRING)
+            }
+        );
+    }
+
+    #[test]
+    fn converts_long_tags() {
+        assert_eq!(
+            jaeger::Tag::from(Tag::new("key", -42i64)),
+            jaeger::Tag {
+                v_long: Some(-42),
+                ..empty_tag(jaeger::TagType::LONG)
+            }
+        );
+    }
+
+    #[test]
+    fn converts_double_tags() {
+        assert_eq!(
+            jaeger::Tag::from(Tag::new("key", 0.25)),
+            jaeger::Tag {
+                v_double: Some(OrderedFloat(0.25)),
+                ..empty_tag(jaeger::TagType::DOUBLE)
+            }
+        );
+    }
+
+    #[test]
+    fn converts_bool_tags() {
+        assert_eq!(
+            jaeger::Tag::from(Tag::new("key", true)),
+            jaeger::Tag {
+                v_bool: Some(true),
+                ..empty_tag(jaeger::TagType::BOOL)
+            }
+        );
+    }
+
+    #[test]
+    fn converts_binary_tags() {
+        assert_eq!(
+            jaeger::Tag::from(Tag::new("key", vec![0u8, 1, 255])),
+            jaeger::Tag {
+                v_binary: Some(vec![0, 1, 255]),
+                ..empty_tag(jaeger::TagType::BINARY)
+            }
+        );
+    }
+
+    #[test]
+    fn converts_spans() {
+        let trace_id = TraceId {
+            high: 0x0102,
+            low: 0x0304,
+        };
+        let parent = SpanState::new(trace_id.clone(), 0x10, true);
+        let previous = SpanState::new(
+            TraceId {
+                high: 0,
+                low: 0x0506,
+            },
+            0x20,
+            true,
+        );
+        let mut state = SpanState::from_parent(parent.clone());
+        state.span_id = 0x30;
+        let start_time = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+
+        let sink = Arc::new(testing::CollectingSink::default());
+        let mut span = Span::new(
+            sink.clone(),
+            "op",
+            start_time,
+            vec![Tag::new("http.status_code", 200i64)],
+            vec![
+                SpanReference::ChildOf(parent),
+                SpanReference::FollowsFrom(previous),
+            ],
+            state,
+            Vec::new(),
+        );
+        span.log_at(
+            start_time + Duration::from_millis(5),
+            vec![Tag::new("event", "retry")],
+        );
+        span.finish();
+        let span = jaeger::Span::from(sink.spans.lock().unwrap().pop().unwrap());
+
+        assert_eq!(span.trace_id_low, 0x0304);
+        assert_eq!(span.trace_id_high, 0x0102);
+        assert_eq!(span.span_id, 0x30);
+        assert_eq!(span.parent_span_id, 0x10);
+        assert_eq!(span.operation_name, "op");
+        assert_eq!(span.flags, 1);
+        assert_eq!(span.start_time, 1_500_000_000_000_000);
+        assert_eq!(span.incomplete, None);
+        assert_eq!(
+            span.references,
+            Some(vec![
+                jaeger::SpanRef::new(jaeger::SpanRefType::CHILD_OF, 0x0304, 0x0102, 0x10),
+                jaeger::SpanRef::new(jaeger::SpanRefType::FOLLOWS_FROM, 0x0506, 0, 0x20),
+            ])
+        );
+        assert_eq!(
+            span.tags,
+            Some(vec![jaeger::Tag::from(Tag::new(
+                "http.status_code",
+                200i64
+            ))])
+        );
+        assert_eq!(
+            span.logs,
+            Some(vec![jaeger::Log::new(
+                1_500_000_000_005_000,
+                vec![jaeger::Tag::from(Tag::new("event", "retry"))],
+            )])
+        );
+    }
+
+    #[test]
+    fn leaves_empty_lists_unset() {
+        let span = testing::finished_span("op", SpanState::default(), Vec::new());
+        let span = jaeger::Span::from(span);
+
+        assert_eq!(span.parent_span_id, 0);
+        assert_eq!(span.references, None);
+        assert_eq!(span.tags, None);
+        assert_eq!(span.logs, None);
+    }
+
+    #[test]
+    fn converts_timestamps_to_micros() {
+        let timestamp = UNIX_EPOCH + Duration::new(1_500_000_000, 123_456_789);
+        let log = jaeger::Log::from(Log::new(timestamp, Vec::new()));
+        assert_eq!(log.timestamp, 1_500_000_000_123_456);
+    }
+
+    #[test]
+    fn clamps_pre_epoch_log_timestamps() {
+        let timestamp = UNIX_EPOCH - Duration::from_secs(60);
+        let log = jaeger::Log::from(Log::new(timestamp, vec![Tag::new("event", "early")]));
+        assert_eq!(log.timestamp, 0);
+        assert_eq!(log.fields.len(), 1);
+    }
+
+    #[test]
+    fn clamps_pre_epoch_start_times() {
+        let span = testing::finished_span_at(
+            "early",
+            SpanState::default(),
+            UNIX_EPOCH - Duration::from_secs(60),
+            Vec::new(),
+        );
+
+        let span = jaeger::Span::from(span);
+        assert_eq!(span.start_time, 0);
+        assert!(span.duration > 0);
+    }
+
+    #[test]
+    fn saturates_huge_durations() {
+        assert_eq!(to_micros(Duration::from_secs(u64::MAX)), i64::MAX);
+    }
+}
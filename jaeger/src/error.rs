@@ -0,0 +1,153 @@
+use std::error;
+use std::fmt;
+use std::io;
+use std::result;
+use std::sync::{Arc, RwLock};
+
+use crate::RuleSamplerError;
+#[cfg(feature = "tokio")]
+use crate::{HttpTransportError, RemoteSamplerError};
+
+pub type Result<T> = result::Result<T, Error>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// A sampling rate outside of `[0.0, 1.0]`.
+    InvalidSamplingRate(f64),
+    /// A negative or NaN rate limit, in traces or spans per second, or a zero
+    /// one where it would allow nothing.
+    InvalidRateLimit(f64),
+    /// Any other invalid configuration value.
+    InvalidConfig(String),
+    Io(io::Error),
+    Encode(thrift::Error),
+    #[cfg(feature = "tokio")]
+    Http(HttpTransportError),
+    #[cfg(feature = "tokio")]
+    RemoteSampler(RemoteSamplerError),
+    SamplingRules(RuleSamplerError),
+    /// The tracer did not finish reporting its spans before the close
+    /// deadline.
+    Timeout,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidSamplingRate(rate) => write!(
+                f,
+                "sampling rate must be between 0.0 and 1.0, received {}",
+                rate
+            ),
+            Error::InvalidRateLimit(rate) => write!(f, "invalid rate limit, received {}", rate),
+            Error::InvalidConfig(reason) => write!(f, "invalid configuration: {}", reason),
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Encode(err) => write!(f, "failed to encode spans: {}", err),
+            #[cfg(feature = "tokio")]
+            Error::Http(err) => err.fmt(f),
+            #[cfg(feature = "tokio")]
+            Error::RemoteSampler(err) => err.fmt(f),
+            Error::SamplingRules(err) => err.fmt(f),
+            Error::Timeout => write!(f, "timed out reporting the remaining spans"),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            Error::Encode(err) => Some(err),
+            #[cfg(feature = "tokio")]
+            Error::Http(err) => Some(err),
+            #[cfg(feature = "tokio")]
+            Error::RemoteSampler(err) => Some(err),
+            Error::SamplingRules(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<thrift::Error> for Error {
+    fn from(err: thrift::Error) -> Self {
+        Error::Encode(err)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl From<HttpTransportError> for Error {
+    fn from(err: HttpTransportError) -> Self {
+        Error::Http(err)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl From<RemoteSamplerError> for Error {
+    fn from(err: RemoteSamplerError) -> Self {
+        Error::RemoteSampler(err)
+    }
+}
+
+impl From<RuleSamplerError> for Error {
+    fn from(err: RuleSamplerError) -> Self {
+        Error::SamplingRules(err)
+    }
+}
+
+/// Receives the errors of the background reporting pipeline, such as spans
+/// failing to encode or batches failing to send, and of the remote sampler.
+pub trait ErrorHandler: Send + Sync {
+    fn handle(&self, error: Error);
+}
+
+impl<F> ErrorHandler for F
+where
+    F: Fn(Error) + Send + Sync,
+{
+    fn handle(&self, error: Error) {
+        self(error)
+    }
+}
+
+/// Prints errors to stderr, the default handler.
+#[derive(Default)]
+pub struct LoggingErrorHandler {}
+
+impl ErrorHandler for LoggingErrorHandler {
+    fn handle(&self, error: Error) {
+        eprintln!("opentracing-rs-jaeger: {}", error);
+    }
+}
+
+/// Forwards errors to the handler set last, `LoggingErrorHandler` until one
+/// is set. Lets components built before the handler is known report to it.
+pub(crate) struct ForwardingErrorHandler {
+    target: RwLock<Arc<dyn ErrorHandler>>,
+}
+
+impl ForwardingErrorHandler {
+    pub(crate) fn set(&self, target: Arc<dyn ErrorHandler>) {
+        *self.target.write().unwrap() = target;
+    }
+}
+
+impl Default for ForwardingErrorHandler {
+    fn default() -> Self {
+        Self {
+            target: RwLock::new(Arc::new(LoggingErrorHandler::default())),
+        }
+    }
+}
+
+impl ErrorHandler for ForwardingErrorHandler {
+    fn handle(&self, error: Error) {
+        let target = self.target.read().unwrap().clone();
+        target.handle(error);
+    }
+}
@@ -1,19 +1,45 @@
-#[macro_use]
-extern crate futures;
-
 mod codec;
+mod error;
+mod propagation;
+mod queue;
 mod reporter;
+mod runtime;
 mod sampler;
+mod signal;
 mod span;
 mod tag;
+#[cfg(test)]
+mod testing;
+#[allow(warnings, clippy::all)]
 mod thrift_gen;
+mod throttler;
 mod tracer;
 mod transport;
 
 pub use crate::{
-    reporter::{LoggingReporter, NullReporter, RemoteReporter, Reporter},
-    sampler::{ConstSampler, ProbabilisticSampler, Sampler},
-    span::{Span, SpanBuilder, SpanState, TraceId},
-    tracer::{Process, Tracer, TracerBuilder},
-    transport::{SpanBatch, Transport, TransportProtocol, UdpTransport},
+    error::{Error, ErrorHandler, LoggingErrorHandler, Result},
+    propagation::{
+        B3Propagator, BasicPropagator, CompositePropagator, JaegerPropagator, TextMapPropagator,
+        W3CPropagator,
+    },
+    queue::DropPolicy,
+    reporter::{
+        LoggingReporter, NullReporter, RemoteReporter, Reporter, TailSamplingPolicy,
+        TailSamplingReporter, TailSamplingReporterBuilder,
+    },
+    sampler::{
+        AdaptiveSampler, AdaptiveSamplerBuilder, ConstSampler, DeferredSampler,
+        GuaranteedThroughputSampler, OperationRate, PerOperationSampler, ProbabilisticSampler,
+        RateLimitingSampler, RuleSampler, RuleSamplerError, Sampler, SamplingDecision,
+        SamplingRule, TagRule,
+    },
+    span::{Span, SpanBuilder, SpanContext, SpanFlags, SpanReference, SpanState, TraceId},
+    throttler::{DebugThrottler, RateLimitingDebugThrottler},
+    tracer::{Process, Tracer, TracerBuilder, DEFAULT_SPAN_QUEUE_CAPACITY},
+    transport::{SpanBatch, Transport, TransportProtocol, UdpTransport, UdpTransportBuilder},
+};
+#[cfg(feature = "tokio")]
+pub use crate::{
+    sampler::{RemoteSampler, RemoteSamplerBuilder, RemoteSamplerError},
+    transport::{HttpTransport, HttpTransportBuilder, HttpTransportError},
 };
@@ -0,0 +1,439 @@
+use opentracing_rs_core::{PropagationError, TextMapReader, TextMapWriter};
+
+use crate::{SpanContext, SpanState, TextMapPropagator, TraceId};
+
+const TRACE_ID_HEADER: &str = "x-b3-traceid";
+const SPAN_ID_HEADER: &str = "x-b3-spanid";
+const PARENT_SPAN_ID_HEADER: &str = "x-b3-parentspanid";
+const SAMPLED_HEADER: &str = "x-b3-sampled";
+const FLAGS_HEADER: &str = "x-b3-flags";
+const SINGLE_HEADER: &str = "b3";
+
+/// Propagates span contexts with Zipkin B3 headers.
+///
+/// Extraction understands both the multi-header `X-B3-*` encoding and the
+/// single `b3` header, preferring the latter when both are present.
+/// Injection writes the encoding the propagator was created with.
+///
+/// A context without sampling state defers the decision: the local sampler
+/// decides on its child spans, as it would on a new trace.
+pub struct B3Propagator {
+    single_header: bool,
+}
+
+impl B3Propagator {
+    /// Writes `X-B3-TraceId`, `X-B3-SpanId`, `X-B3-ParentSpanId`,
+    /// `X-B3-Sampled` and `X-B3-Flags`.
+    pub fn multi_header() -> Self {
+        Self {
+            single_header: false,
+        }
+    }
+
+    /// Writes a single `b3: {trace-id}-{span-id}-{sampling}-{parent-span-id}`
+    /// header.
+    pub fn single_header() -> Self {
+        Self {
+            single_header: true,
+        }
+    }
+}
+
+impl Default for B3Propagator {
+    fn default() -> Self {
+        Self::multi_header()
+    }
+}
+
+impl TextMapPropagator for B3Propagator {
+    fn inject(&self, context: &SpanContext, carrier: &mut dyn TextMapWriter) {
+        let state = context.state();
+        let trace_id = state.trace_id.to_string();
+        let span_id = format!("{:016x}", state.span_id);
+
+        // Leaving out the sampling state defers the decision downstream, as
+        // long as ours is not made either.
+        let sampling = if state.is_debug() {
+            Some("d")
+        } else if state.is_sampled() {
+            Some("1")
+        } else if state.is_sampling_final() {
+            Some("0")
+        } else {
+            None
+        };
+
+        if self.single_header {
+            // The parent span id can only follow a sampling state.
+            let value = match (sampling, state.parent_span_id) {
+                (Some(sampling), Some(parent_span_id)) => format!(
+                    "{}-{}-{}-{:016x}",
+                    trace_id, span_id, sampling, parent_span_id
+                ),
+                (Some(sampling), None) => format!("{}-{}-{}", trace_id, span_id, sampling),
+                (None, _) => format!("{}-{}", trace_id, span_id),
+            };
+            carrier.set(SINGLE_HEADER, &value);
+            return;
+        }
+
+        carrier.set(TRACE_ID_HEADER, &trace_id);
+        carrier.set(SPAN_ID_HEADER, &span_id);
+        if let Some(parent_span_id) = state.parent_span_id {
+            carrier.set(PARENT_SPAN_ID_HEADER, &format!("{:016x}", parent_span_id));
+        }
+        // Debug implies sampled, so `X-B3-Sampled` is redundant with `X-B3-Flags`.
+        match sampling {
+            Some("d") => carrier.set(FLAGS_HEADER, "1"),
+            Some(sampling) => carrier.set(SAMPLED_HEADER, sampling),
+            None => {}
+        }
+    }
+
+    fn extract(
+        &self,
+        carrier: &dyn TextMapReader,
+    ) -> Result<Option<SpanContext>, PropagationError> {
+        let mut single = None;
+        let mut trace_id = None;
+        let mut span_id = None;
+        let mut parent_span_id = None;
+        let mut sampled = None;
+        let mut flags = None;
+
+        for key in carrier.keys() {
+            let value = match carrier.get(key) {
+                Some(value) => value.trim(),
+                None => continue,
+            };
+
+            match key.to_ascii_lowercase().as_str() {
+                SINGLE_HEADER => single = Some(value),
+                TRACE_ID_HEADER => trace_id = Some(value),
+                SPAN_ID_HEADER => span_id = Some(value),
+                PARENT_SPAN_ID_HEADER => parent_span_id = Some(value),
+                SAMPLED_HEADER => sampled = Some(value),
+                FLAGS_HEADER => flags = Some(value),
+                _ => {}
+            }
+        }
+
+        if let Some(single) = single {
+            return extract_single_header(single);
+        }
+
+        let (trace_id, span_id) = match (trace_id, span_id) {
+            (Some(trace_id), Some(span_id)) => (trace_id, span_id),
+            (None, None) => return Ok(None),
+            _ => {
+                return Err(corrupted(
+                    "X-B3-TraceId and X-B3-SpanId must be set together",
+                ))
+            }
+        };
+
+        let is_debug = match flags {
+            None | Some("0") => false,
+            Some("1") => true,
+            Some(flags) => return Err(corrupted(&format!("invalid X-B3-Flags {:?}", flags))),
+        };
+        // Without `X-B3-Sampled` the caller defers the decision to us.
+        let is_sampled = match sampled {
+            None => None,
+            Some("0") | Some("false") => Some(false),
+            Some("1") | Some("true") => Some(true),
+            Some(sampled) => return Err(corrupted(&format!("invalid X-B3-Sampled {:?}", sampled))),
+        };
+
+        let state = build_state(trace_id, span_id, parent_span_id, is_sampled, is_debug)?;
+        Ok(Some(SpanContext::new(state, Vec::new())))
+    }
+}
+
+fn extract_single_header(value: &str) -> Result<Option<SpanContext>, PropagationError> {
+    let parts: Vec<&str> = value.split('-').collect();
+
+    // A lone sampling state carries no span context to continue from.
+    if parts.len() == 1 {
+        return match parts[0] {
+            "0" | "1" | "d" => Ok(None),
+            _ => Err(corrupted(&format!("invalid b3 value {:?}", value))),
+        };
+    }
+    if parts.len() > 4 {
+        return Err(corrupted(&format!("invalid b3 value {:?}", value)));
+    }
+
+    let (is_sampled, is_debug) = match parts.get(2) {
+        None => (None, false),
+        Some(&"0") => (Some(false), false),
+        Some(&"1") => (Some(true), false),
+        Some(&"d") => (Some(true), true),
+        Some(_) => return Err(corrupted(&format!("invalid b3 value {:?}", value))),
+    };
+
+    let state = build_state(
+        parts[0],
+        parts[1],
+        parts.get(3).cloned(),
+        is_sampled,
+        is_debug,
+    )?;
+    Ok(Some(SpanContext::new(state, Vec::new())))
+}
+
+fn build_state(
+    trace_id: &str,
+    span_id: &str,
+    parent_span_id: Option<&str>,
+    is_sampled: Option<bool>,
+    is_debug: bool,
+) -> Result<SpanState, PropagationError> {
+    let trace_id = match trace_id.len() {
+        16 | 32 => TraceId::from_hex(trace_id),
+        _ => None,
+    }
+    .filter(|id| id.high != 0 || id.low != 0)
+    .ok_or_else(|| corrupted(&format!("invalid trace id {:?}", trace_id)))?;
+
+    let span_id = parse_span_id(span_id)?;
+    let parent_span_id = match parent_span_id {
+        Some(parent_span_id) => Some(parse_span_id(parent_span_id)?),
+        None => None,
+    };
+
+    // Debug implies sampled, whatever the sampling state says.
+    let mut state = match is_sampled {
+        _ if is_debug => SpanState::new(trace_id, span_id, true),
+        Some(is_sampled) => SpanState::new(trace_id, span_id, is_sampled),
+        None => SpanState::undecided(trace_id, span_id),
+    };
+    state.parent_span_id = parent_span_id;
+    state.update_flags(|flags| flags.set_debug(is_debug));
+    Ok(state)
+}
+
+fn parse_span_id(s: &str) -> Result<u64, PropagationError> {
+    if s.len() != 16 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(corrupted(&format!("invalid span id {:?}", s)));
+    }
+    match u64::from_str_radix(s, 16) {
+        Ok(id) if id != 0 => Ok(id),
+        _ => Err(corrupted(&format!("invalid span id {:?}", s))),
+    }
+}
+
+fn corrupted(reason: &str) -> PropagationError {
+    PropagationError::SpanContextCorrupted(reason.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+    const SPAN_ID: &str = "00f067aa0ba902b7";
+    const PARENT_SPAN_ID: &str = "a2fb4a1d1a96d312";
+
+    fn carrier(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn extract(entries: &[(&str, &str)]) -> Result<Option<SpanContext>, PropagationError> {
+        B3Propagator::default().extract(&carrier(entries))
+    }
+
+    fn assert_corrupted(entries: &[(&str, &str)]) {
+        match extract(entries) {
+            Err(PropagationError::SpanContextCorrupted(_)) => {}
+            other => panic!("extracted {:?} from {:?}", other.map(|_| ()), entries),
+        }
+    }
+
+    #[test]
+    fn extracts_multi_header() {
+        let context = extract(&[
+            ("X-B3-TraceId", TRACE_ID),
+            ("X-B3-SpanId", SPAN_ID),
+            ("X-B3-ParentSpanId", PARENT_SPAN_ID),
+            ("X-B3-Sampled", "1"),
+        ])
+        .unwrap()
+        .unwrap();
+        let state = context.state();
+        assert_eq!(
+            state.trace_id(),
+            &TraceId {
+                high: 0x4bf9_2f35_77b3_4da6,
+                low: 0xa3ce_929d_0e0e_4736,
+            }
+        );
+        assert_eq!(state.span_id(), 0x00f0_67aa_0ba9_02b7);
+        assert_eq!(state.parent_span_id(), Some(0xa2fb_4a1d_1a96_d312));
+        assert!(state.is_sampled());
+        assert!(!state.is_debug());
+        assert!(state.is_sampling_final());
+    }
+
+    #[test]
+    fn extracts_multi_header_sampling_state() {
+        let extract_state = |entries: &[(&str, &str)]| {
+            let mut entries = entries.to_vec();
+            entries.push(("x-b3-traceid", "a3ce929d0e0e4736"));
+            entries.push(("x-b3-spanid", SPAN_ID));
+            extract(&entries).unwrap().unwrap().state().clone()
+        };
+
+        let state = extract_state(&[("x-b3-sampled", "true")]);
+        assert!(state.is_sampled() && state.is_sampling_final());
+        let state = extract_state(&[("x-b3-sampled", "0")]);
+        assert!(!state.is_sampled() && state.is_sampling_final());
+        let state = extract_state(&[("x-b3-sampled", "false"), ("x-b3-flags", "0")]);
+        assert!(!state.is_sampled() && state.is_sampling_final());
+        let state = extract_state(&[("x-b3-flags", "1")]);
+        assert!(state.is_sampled() && state.is_debug() && state.is_sampling_final());
+
+        let state = extract_state(&[]);
+        assert!(!state.is_sampled() && !state.is_sampling_final());
+        let state = extract_state(&[("x-b3-flags", "0")]);
+        assert!(!state.is_sampled() && !state.is_sampling_final());
+    }
+
+    #[test]
+    fn extracts_single_header() {
+        let value = format!("{}-{}-1-{}", TRACE_ID, SPAN_ID, PARENT_SPAN_ID);
+        let context = extract(&[("B3", &value)]).unwrap().unwrap();
+        let state = context.state();
+        assert_eq!(state.span_id(), 0x00f0_67aa_0ba9_02b7);
+        assert_eq!(state.parent_span_id(), Some(0xa2fb_4a1d_1a96_d312));
+        assert!(state.is_sampled() && state.is_sampling_final());
+
+        let value = format!("{}-{}-d", TRACE_ID, SPAN_ID);
+        let state = extract(&[("b3", &value)]).unwrap().unwrap().state().clone();
+        assert!(state.is_sampled() && state.is_debug() && state.is_sampling_final());
+
+        let value = format!("{}-{}-0", TRACE_ID, SPAN_ID);
+        let state = extract(&[("b3", &value)]).unwrap().unwrap().state().clone();
+        assert!(!state.is_sampled() && state.is_sampling_final());
+
+        let value = format!("{}-{}", TRACE_ID, SPAN_ID);
+        let state = extract(&[("b3", &value)]).unwrap().unwrap().state().clone();
+        assert!(!state.is_sampled() && !state.is_sampling_final());
+    }
+
+    #[test]
+    fn prefers_single_header() {
+        let value = format!("{}-{}-1", TRACE_ID, SPAN_ID);
+        let context = extract(&[
+            ("b3", &value),
+            ("x-b3-traceid", TRACE_ID),
+            ("x-b3-spanid", PARENT_SPAN_ID),
+            ("x-b3-sampled", "0"),
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(context.state().span_id(), 0x00f0_67aa_0ba9_02b7);
+        assert!(context.state().is_sampled());
+    }
+
+    #[test]
+    fn extracts_nothing_without_span_context() {
+        assert!(extract(&[]).unwrap().is_none());
+        assert!(extract(&[("x-b3-sampled", "1")]).unwrap().is_none());
+        for sampling in &["0", "1", "d"] {
+            assert!(extract(&[("b3", sampling)]).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_multi_header() {
+        assert_corrupted(&[("x-b3-traceid", TRACE_ID)]);
+        assert_corrupted(&[("x-b3-spanid", SPAN_ID)]);
+        for (key, value) in &[
+            ("x-b3-traceid", "4bf92f3577b34da6a3ce929d0e0e47"),
+            ("x-b3-traceid", "00000000000000000000000000000000"),
+            ("x-b3-traceid", "4bf92f3577b34da6a3ce929d0e0e473g"),
+            ("x-b3-spanid", "f067aa0ba902b7"),
+            ("x-b3-spanid", "0000000000000000"),
+            ("x-b3-parentspanid", "xyz"),
+            ("x-b3-sampled", "yes"),
+            ("x-b3-flags", "2"),
+        ] {
+            let mut entries = vec![("x-b3-traceid", TRACE_ID), ("x-b3-spanid", SPAN_ID)];
+            entries.retain(|(k, _)| k != key);
+            entries.push((key, value));
+            assert_corrupted(&entries);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_single_header() {
+        for value in &[
+            "",
+            "x",
+            "true",
+            TRACE_ID,
+            &format!("{}-{}-2", TRACE_ID, SPAN_ID),
+            &format!("{}-{}-1-{}-0", TRACE_ID, SPAN_ID, PARENT_SPAN_ID),
+            &format!("{}-{}-1-abc", TRACE_ID, SPAN_ID),
+            &format!("{}-0000000000000000-1", TRACE_ID),
+            &format!("abc-{}-1", SPAN_ID),
+        ] {
+            assert_corrupted(&[("b3", value)]);
+        }
+    }
+
+    fn sample_states() -> Vec<SpanState> {
+        let trace_id = TraceId {
+            high: 0x0102,
+            low: 0xdead_beef,
+        };
+
+        let mut sampled = SpanState::new(trace_id.clone(), 0x42, true);
+        sampled.parent_span_id = Some(0x24);
+        let not_sampled = SpanState::new(trace_id.clone(), 0x42, false);
+        let debug = SpanState::new(trace_id.clone(), 0x42, true);
+        debug.update_flags(|flags| flags.set_debug(true));
+        let undecided = SpanState::undecided(trace_id, 0x42);
+
+        vec![sampled, not_sampled, debug, undecided]
+    }
+
+    #[test]
+    fn round_trips() {
+        for propagator in &[B3Propagator::multi_header(), B3Propagator::single_header()] {
+            for state in sample_states() {
+                let context = SpanContext::new(state, Vec::new());
+                let mut carrier = HashMap::new();
+                propagator.inject(&context, &mut carrier);
+
+                let extracted = propagator.extract(&carrier).unwrap().unwrap();
+                let (state, expected) = (extracted.state(), context.state());
+                assert_eq!(state.trace_id(), expected.trace_id());
+                assert_eq!(state.span_id(), expected.span_id());
+                assert_eq!(state.parent_span_id(), expected.parent_span_id());
+                assert_eq!(state.flags().bits(), expected.flags().bits());
+                assert_eq!(state.is_sampling_final(), expected.is_sampling_final());
+            }
+        }
+    }
+
+    #[test]
+    fn injects_nothing_for_undecided_sampling() {
+        let context = SpanContext::new(SpanState::undecided(TraceId::new(), 0x42), Vec::new());
+
+        let mut carrier = HashMap::new();
+        B3Propagator::multi_header().inject(&context, &mut carrier);
+        assert!(!carrier.contains_key(SAMPLED_HEADER));
+        assert!(!carrier.contains_key(FLAGS_HEADER));
+
+        let mut carrier = HashMap::new();
+        B3Propagator::single_header().inject(&context, &mut carrier);
+        assert_eq!(carrier[SINGLE_HEADER].split('-').count(), 2);
+    }
+}
@@ -0,0 +1,169 @@
+use opentracing_rs_core::{BaggageItem, PropagationError, TextMapReader, TextMapWriter};
+
+use crate::{SpanContext, SpanState, TextMapPropagator, TraceId};
+
+const TRACE_ID_KEY: &str = "ot-tracer-traceid";
+const SPAN_ID_KEY: &str = "ot-tracer-spanid";
+const SAMPLED_KEY: &str = "ot-tracer-sampled";
+const BAGGAGE_KEY_PREFIX: &str = "ot-baggage-";
+
+/// Propagates span contexts with the `ot-tracer-*` and `ot-baggage-*` keys of
+/// the OpenTracing basic tracer.
+#[derive(Default)]
+pub struct BasicPropagator {}
+
+impl BasicPropagator {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl TextMapPropagator for BasicPropagator {
+    fn inject(&self, context: &SpanContext, carrier: &mut dyn TextMapWriter) {
+        let state = context.state();
+
+        carrier.set(TRACE_ID_KEY, &state.trace_id.to_string());
+        carrier.set(SPAN_ID_KEY, &format!("{:x}", state.span_id));
+        carrier.set(
+            SAMPLED_KEY,
+            if state.is_sampled() { "true" } else { "false" },
+        );
+
+        for (key, value) in context.baggage_items() {
+            carrier.set(&format!("{}{}", BAGGAGE_KEY_PREFIX, key), value);
+        }
+    }
+
+    fn extract(
+        &self,
+        carrier: &dyn TextMapReader,
+    ) -> Result<Option<SpanContext>, PropagationError> {
+        let mut trace_id = None;
+        let mut span_id = None;
+        let mut is_sampled = false;
+        let mut baggage_items = Vec::new();
+
+        for key in carrier.keys() {
+            let value = match carrier.get(key) {
+                Some(value) => value,
+                None => continue,
+            };
+            let lower_key = key.to_ascii_lowercase();
+
+            match lower_key.as_str() {
+                TRACE_ID_KEY => {
+                    trace_id = Some(TraceId::from_hex(value).ok_or_else(|| {
+                        PropagationError::SpanContextCorrupted(format!(
+                            "invalid trace id {:?}",
+                            value
+                        ))
+                    })?)
+                }
+                SPAN_ID_KEY => {
+                    span_id = Some(u64::from_str_radix(value, 16).map_err(|_| {
+                        PropagationError::SpanContextCorrupted(format!(
+                            "invalid span id {:?}",
+                            value
+                        ))
+                    })?)
+                }
+                SAMPLED_KEY => is_sampled = value == "true",
+                _ if lower_key.starts_with(BAGGAGE_KEY_PREFIX) => {
+                    baggage_items.push(BaggageItem::new(
+                        &lower_key[BAGGAGE_KEY_PREFIX.len()..],
+                        value,
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        match (trace_id, span_id) {
+            (Some(trace_id), Some(span_id)) => Ok(Some(SpanContext::new(
+                SpanState::new(trace_id, span_id, is_sampled),
+                baggage_items,
+            ))),
+            (None, None) => Ok(None),
+            _ => Err(PropagationError::SpanContextCorrupted(
+                "trace id and span id must be set together".to_owned(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn carrier(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn round_trips() {
+        let state = SpanState::new(
+            TraceId {
+                high: 0x0102,
+                low: 0xdead_beef,
+            },
+            0x42,
+            true,
+        );
+        let mut context = SpanContext::new(state, Vec::new());
+        context.set_baggage_item("user", "alice");
+
+        let mut carrier = HashMap::new();
+        BasicPropagator::new().inject(&context, &mut carrier);
+        assert_eq!(carrier["ot-tracer-spanid"], "42");
+        assert_eq!(carrier["ot-tracer-sampled"], "true");
+        assert_eq!(carrier["ot-baggage-user"], "alice");
+
+        let extracted = BasicPropagator::new().extract(&carrier).unwrap().unwrap();
+        assert_eq!(extracted.state().trace_id(), context.state().trace_id());
+        assert_eq!(extracted.state().span_id(), 0x42);
+        assert!(extracted.state().is_sampled());
+        assert_eq!(extracted.baggage_item("user"), Some("alice"));
+    }
+
+    #[test]
+    fn extracts_keys_in_any_case() {
+        let carrier = carrier(&[
+            ("OT-Tracer-TraceId", "abc"),
+            ("OT-Tracer-SpanId", "1"),
+            ("OT-Baggage-User", "alice"),
+        ]);
+
+        let context = BasicPropagator::new().extract(&carrier).unwrap().unwrap();
+        assert_eq!(context.state().span_id(), 1);
+        assert!(!context.state().is_sampled());
+        assert_eq!(context.baggage_item("user"), Some("alice"));
+    }
+
+    #[test]
+    fn extracts_nothing_without_ids() {
+        let carrier = carrier(&[("ot-baggage-user", "alice")]);
+
+        assert!(BasicPropagator::new().extract(&carrier).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_contexts() {
+        for entries in &[
+            &[("ot-tracer-traceid", "abc")][..],
+            &[("ot-tracer-spanid", "1")],
+            &[("ot-tracer-traceid", "xyz"), ("ot-tracer-spanid", "1")],
+            &[("ot-tracer-traceid", "abc"), ("ot-tracer-spanid", "xyz")],
+        ] {
+            assert!(
+                BasicPropagator::new().extract(&carrier(entries)).is_err(),
+                "extracted {:?}",
+                entries
+            );
+        }
+    }
+}
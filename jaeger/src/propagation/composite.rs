@@ -0,0 +1,123 @@
+use opentracing_rs_core::{PropagationError, TextMapReader, TextMapWriter};
+
+use crate::{SpanContext, TextMapPropagator};
+
+/// Combines several propagators into one.
+///
+/// Injection writes the span context with every propagator. Extraction tries
+/// the propagators in order and returns the first span context found; an error
+/// is only returned if no propagator succeeds.
+#[derive(Default)]
+pub struct CompositePropagator {
+    propagators: Vec<Box<dyn TextMapPropagator>>,
+}
+
+impl CompositePropagator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with<P>(mut self, propagator: P) -> Self
+    where
+        P: TextMapPropagator + 'static,
+    {
+        self.propagators.push(Box::new(propagator));
+        self
+    }
+}
+
+impl TextMapPropagator for CompositePropagator {
+    fn inject(&self, context: &SpanContext, carrier: &mut dyn TextMapWriter) {
+        for propagator in &self.propagators {
+            propagator.inject(context, carrier);
+        }
+    }
+
+    fn extract(
+        &self,
+        carrier: &dyn TextMapReader,
+    ) -> Result<Option<SpanContext>, PropagationError> {
+        let mut error = None;
+
+        for propagator in &self.propagators {
+            match propagator.extract(carrier) {
+                Ok(Some(context)) => return Ok(Some(context)),
+                Ok(None) => {}
+                Err(err) => {
+                    if error.is_none() {
+                        error = Some(err);
+                    }
+                }
+            }
+        }
+
+        match error {
+            Some(err) => Err(err),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{B3Propagator, JaegerPropagator, SpanState, TraceId, W3CPropagator};
+
+    fn composite() -> CompositePropagator {
+        CompositePropagator::new()
+            .with(W3CPropagator::new())
+            .with(B3Propagator::multi_header())
+            .with(JaegerPropagator::http_headers())
+    }
+
+    fn carrier(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn injects_every_format() {
+        let context = SpanContext::new(SpanState::new(TraceId::new(), 0x42, true), Vec::new());
+
+        let mut carrier = HashMap::new();
+        composite().inject(&context, &mut carrier);
+
+        assert!(carrier.contains_key("traceparent"));
+        assert!(carrier.contains_key("x-b3-traceid"));
+        assert!(carrier.contains_key("uber-trace-id"));
+    }
+
+    #[test]
+    fn extracts_with_first_succeeding_propagator() {
+        let carrier = carrier(&[
+            ("X-B3-TraceId", "0000000000000001"),
+            ("X-B3-SpanId", "0000000000000002"),
+            ("X-B3-Sampled", "1"),
+            ("uber-trace-id", "3:4:0:1"),
+        ]);
+
+        let context = composite().extract(&carrier).unwrap().unwrap();
+        assert_eq!(context.state().span_id(), 2);
+    }
+
+    #[test]
+    fn skips_failing_propagators() {
+        let carrier = carrier(&[("traceparent", "garbage"), ("uber-trace-id", "3:4:0:1")]);
+
+        let context = composite().extract(&carrier).unwrap().unwrap();
+        assert_eq!(context.state().span_id(), 4);
+    }
+
+    #[test]
+    fn reports_first_error_when_nothing_is_extracted() {
+        let corrupt = carrier(&[("traceparent", "garbage")]);
+        assert!(composite().extract(&corrupt).is_err());
+
+        let empty = carrier(&[("other", "value")]);
+        assert!(composite().extract(&empty).unwrap().is_none());
+    }
+}
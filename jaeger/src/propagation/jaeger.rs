@@ -0,0 +1,306 @@
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use opentracing_rs_core::{BaggageItem, PropagationError, TextMapReader, TextMapWriter};
+
+use crate::{SpanContext, SpanFlags, SpanState, TextMapPropagator, TraceId};
+
+const TRACE_CONTEXT_HEADER: &str = "uber-trace-id";
+const BAGGAGE_HEADER_PREFIX: &str = "uberctx-";
+
+/// Characters left unescaped by URL encoding, as in RFC 3986.
+const URL_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Propagates span contexts in the native Jaeger format used by the Go and
+/// Java clients.
+///
+/// The context is written to `uber-trace-id` as
+/// `{trace-id}:{span-id}:{parent-span-id}:{flags}` in hex, and every baggage
+/// item to its own `uberctx-{key}` entry.
+pub struct JaegerPropagator {
+    url_encoding: bool,
+}
+
+impl JaegerPropagator {
+    /// Propagator for the `TextMap` format, values are written verbatim.
+    pub fn text_map() -> Self {
+        Self {
+            url_encoding: false,
+        }
+    }
+
+    /// Propagator for the `HttpHeaders` format, values are URL-encoded.
+    pub fn http_headers() -> Self {
+        Self { url_encoding: true }
+    }
+
+    fn encode_value(&self, value: &str) -> String {
+        if self.url_encoding {
+            utf8_percent_encode(value, URL_ENCODE_SET).to_string()
+        } else {
+            value.to_owned()
+        }
+    }
+
+    fn decode_value(&self, value: &str) -> Result<String, PropagationError> {
+        if self.url_encoding {
+            percent_decode_str(value)
+                .decode_utf8()
+                .map(|v| v.into_owned())
+                .map_err(|_| {
+                    PropagationError::SpanContextCorrupted(format!(
+                        "invalid URL-encoded value {:?}",
+                        value
+                    ))
+                })
+        } else {
+            Ok(value.to_owned())
+        }
+    }
+}
+
+impl Default for JaegerPropagator {
+    fn default() -> Self {
+        Self::http_headers()
+    }
+}
+
+impl TextMapPropagator for JaegerPropagator {
+    fn inject(&self, context: &SpanContext, carrier: &mut dyn TextMapWriter) {
+        let state = context.state();
+        let trace_context = format!(
+            "{}:{:x}:{:x}:{:x}",
+            state.trace_id,
+            state.span_id,
+            state.parent_span_id.unwrap_or_default(),
+            state.flags().bits()
+        );
+        carrier.set(TRACE_CONTEXT_HEADER, &trace_context);
+
+        for (key, value) in context.baggage_items() {
+            carrier.set(
+                &format!("{}{}", BAGGAGE_HEADER_PREFIX, key),
+                &self.encode_value(value),
+            );
+        }
+    }
+
+    fn extract(
+        &self,
+        carrier: &dyn TextMapReader,
+    ) -> Result<Option<SpanContext>, PropagationError> {
+        let mut state = None;
+        let mut baggage_items = Vec::new();
+
+        for key in carrier.keys() {
+            let value = match carrier.get(key) {
+                Some(value) => value,
+                None => continue,
+            };
+            let lower_key = key.to_ascii_lowercase();
+
+            if lower_key == TRACE_CONTEXT_HEADER {
+                state = Some(parse_trace_context(&self.decode_value(value)?)?);
+            } else if let Some(baggage_key) = lower_key.strip_prefix(BAGGAGE_HEADER_PREFIX) {
+                baggage_items.push(BaggageItem::new(baggage_key, &self.decode_value(value)?));
+            }
+        }
+
+        Ok(state.map(|state| SpanContext::new(state, baggage_items)))
+    }
+}
+
+fn parse_trace_context(value: &str) -> Result<SpanState, PropagationError> {
+    let corrupted = || {
+        PropagationError::SpanContextCorrupted(format!(
+            "invalid {} value {:?}",
+            TRACE_CONTEXT_HEADER, value
+        ))
+    };
+
+    let parts: Vec<&str> = value.split(':').collect();
+    if parts.len() != 4 {
+        return Err(corrupted());
+    }
+
+    let trace_id = TraceId::from_hex(parts[0]).ok_or_else(corrupted)?;
+    let span_id = parse_hex_u64(parts[1]).ok_or_else(corrupted)?;
+    let parent_span_id = parse_hex_u64(parts[2]).ok_or_else(corrupted)?;
+    let flags = u8::from_str_radix(parts[3], 16).map_err(|_| corrupted())?;
+
+    if (trace_id.high == 0 && trace_id.low == 0) || span_id == 0 {
+        return Err(corrupted());
+    }
+
+    let mut state = SpanState::new(trace_id, span_id, false);
+    state.set_flags(SpanFlags::from_bits(flags));
+    if parent_span_id != 0 {
+        state.parent_span_id = Some(parent_span_id);
+    }
+
+    Ok(state)
+}
+
+fn parse_hex_u64(s: &str) -> Option<u64> {
+    if s.is_empty() || s.len() > 16 {
+        return None;
+    }
+    u64::from_str_radix(s, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn carrier(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn extracts_trace_context_and_baggage() {
+        let carrier = carrier(&[
+            (
+                "Uber-Trace-Id",
+                "4bf92f3577b34da6a3ce929d0e0e4736:a2fb4a1d1a96d312:1f:3",
+            ),
+            ("uberctx-user", "alice%20smith"),
+            ("other", "ignored"),
+        ]);
+
+        let context = JaegerPropagator::http_headers()
+            .extract(&carrier)
+            .unwrap()
+            .unwrap();
+        let state = context.state();
+        assert_eq!(
+            state.trace_id(),
+            &TraceId {
+                high: 0x4bf9_2f35_77b3_4da6,
+                low: 0xa3ce_929d_0e0e_4736,
+            }
+        );
+        assert_eq!(state.span_id(), 0xa2fb_4a1d_1a96_d312);
+        assert_eq!(state.parent_span_id(), Some(0x1f));
+        assert!(state.is_sampled());
+        assert!(state.is_debug());
+        assert_eq!(context.baggage_item("user"), Some("alice smith"));
+    }
+
+    #[test]
+    fn extracts_short_ids_without_parent() {
+        let carrier = carrier(&[("uber-trace-id", "abc:1:0:0")]);
+
+        let context = JaegerPropagator::text_map()
+            .extract(&carrier)
+            .unwrap()
+            .unwrap();
+        let state = context.state();
+        assert_eq!(
+            state.trace_id(),
+            &TraceId {
+                high: 0,
+                low: 0xabc
+            }
+        );
+        assert_eq!(state.span_id(), 1);
+        assert_eq!(state.parent_span_id(), None);
+        assert!(!state.is_sampled());
+    }
+
+    #[test]
+    fn extracts_nothing_from_empty_carrier() {
+        let carrier = carrier(&[("uberctx-user", "alice")]);
+
+        assert!(JaegerPropagator::default()
+            .extract(&carrier)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_trace_context() {
+        let propagator = JaegerPropagator::text_map();
+        for value in &[
+            "",
+            "abc:1:0",
+            "abc:1:0:1:0",
+            "xyz:1:0:1",
+            "abc:xyz:0:1",
+            "abc::0:1",
+            "abc:1:0:100",
+            "abc:11112222333344445:0:1",
+            "123456789012345678901234567890123:1:0:1",
+            "0:1:0:1",
+            "abc:0:0:1",
+        ] {
+            let carrier = carrier(&[("uber-trace-id", value)]);
+            match propagator.extract(&carrier) {
+                Err(PropagationError::SpanContextCorrupted(_)) => {}
+                other => panic!("extracted {:?} from {:?}", other.map(|_| ()), value),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_url_encoding() {
+        let carrier = carrier(&[("uber-trace-id", "abc:1:0:1"), ("uberctx-user", "%ff")]);
+
+        assert!(JaegerPropagator::http_headers().extract(&carrier).is_err());
+        assert!(JaegerPropagator::text_map().extract(&carrier).is_ok());
+    }
+
+    #[test]
+    fn round_trips() {
+        for propagator in &[
+            JaegerPropagator::text_map(),
+            JaegerPropagator::http_headers(),
+        ] {
+            let mut state = SpanState::new(
+                TraceId {
+                    high: 0x0102,
+                    low: 0xdead_beef,
+                },
+                0x42,
+                true,
+            );
+            state.parent_span_id = Some(0x24);
+            let mut context = SpanContext::new(state, Vec::new());
+            context.set_baggage_item("user", "alice smith");
+            context.set_baggage_item("path", "/a?b=c&d");
+
+            let mut carrier = HashMap::new();
+            propagator.inject(&context, &mut carrier);
+            let extracted = propagator.extract(&carrier).unwrap().unwrap();
+
+            let state = extracted.state();
+            assert_eq!(state.trace_id(), context.state().trace_id());
+            assert_eq!(state.span_id(), 0x42);
+            assert_eq!(state.parent_span_id(), Some(0x24));
+            assert_eq!(state.flags().bits(), context.state().flags().bits());
+            assert_eq!(extracted.baggage_item("user"), Some("alice smith"));
+            assert_eq!(extracted.baggage_item("path"), Some("/a?b=c&d"));
+        }
+    }
+
+    #[test]
+    fn url_encodes_baggage_for_http_headers() {
+        let mut context = SpanContext::new(SpanState::default(), Vec::new());
+        context.set_baggage_item("user", "alice smith");
+
+        let mut carrier = HashMap::new();
+        JaegerPropagator::http_headers().inject(&context, &mut carrier);
+        assert_eq!(carrier["uberctx-user"], "alice%20smith");
+
+        let mut carrier = HashMap::new();
+        JaegerPropagator::text_map().inject(&context, &mut carrier);
+        assert_eq!(carrier["uberctx-user"], "alice smith");
+    }
+}
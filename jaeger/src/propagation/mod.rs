@@ -0,0 +1,218 @@
+use std::io::{Read, Write};
+
+use opentracing_rs_core::{BaggageItem, PropagationError, TextMapReader, TextMapWriter};
+
+use crate::{SpanContext, SpanFlags, SpanState, TraceId};
+
+mod b3;
+mod basic;
+mod composite;
+mod jaeger;
+mod w3c;
+
+pub use self::{
+    b3::B3Propagator, basic::BasicPropagator, composite::CompositePropagator,
+    jaeger::JaegerPropagator, w3c::W3CPropagator,
+};
+
+/// Injects and extracts span contexts for the `TextMap` and `HttpHeaders`
+/// formats.
+pub trait TextMapPropagator: Send + Sync {
+    fn inject(&self, context: &SpanContext, carrier: &mut dyn TextMapWriter);
+    fn extract(&self, carrier: &dyn TextMapReader)
+        -> Result<Option<SpanContext>, PropagationError>;
+}
+
+/// Writes a span context in the Jaeger binary format: big-endian trace id
+/// (high, low), span id, parent span id, a flags byte and the baggage items as
+/// length-prefixed strings.
+pub(crate) fn inject_binary(
+    context: &SpanContext,
+    carrier: &mut dyn Write,
+) -> Result<(), PropagationError> {
+    let state = context.state();
+    let mut buf = Vec::with_capacity(37);
+
+    buf.extend_from_slice(&state.trace_id.high.to_be_bytes());
+    buf.extend_from_slice(&state.trace_id.low.to_be_bytes());
+    buf.extend_from_slice(&state.span_id.to_be_bytes());
+    buf.extend_from_slice(&state.parent_span_id.unwrap_or_default().to_be_bytes());
+    buf.push(state.flags().bits());
+
+    let baggage_items: Vec<(&str, &str)> = context.baggage_items().collect();
+    buf.extend_from_slice(&(baggage_items.len() as u32).to_be_bytes());
+    for (key, value) in baggage_items {
+        for s in &[key, value] {
+            buf.extend_from_slice(&(s.len() as u32).to_be_bytes());
+            buf.extend_from_slice(s.as_bytes());
+        }
+    }
+
+    carrier.write_all(&buf)?;
+    Ok(())
+}
+
+pub(crate) fn extract_binary(
+    carrier: &mut dyn Read,
+) -> Result<Option<SpanContext>, PropagationError> {
+    let mut buf = Vec::new();
+    carrier.read_to_end(&mut buf)?;
+    if buf.is_empty() {
+        return Ok(None);
+    }
+
+    let mut reader = BinaryReader { buf: &buf };
+    let high = reader.read_u64()?;
+    let low = reader.read_u64()?;
+    let span_id = reader.read_u64()?;
+    let parent_span_id = reader.read_u64()?;
+    let flags = reader.read_u8()?;
+
+    let baggage_count = reader.read_u32()?;
+    let mut baggage_items = Vec::new();
+    for _ in 0..baggage_count {
+        let key = reader.read_string()?;
+        let value = reader.read_string()?;
+        baggage_items.push(BaggageItem::new(&key, &value));
+    }
+
+    let mut state = SpanState::new(TraceId { low, high }, span_id, false);
+    state.set_flags(SpanFlags::from_bits(flags));
+    if parent_span_id != 0 {
+        state.parent_span_id = Some(parent_span_id);
+    }
+
+    Ok(Some(SpanContext::new(state, baggage_items)))
+}
+
+struct BinaryReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> BinaryReader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], PropagationError> {
+        if self.buf.len() < len {
+            return Err(PropagationError::SpanContextCorrupted(
+                "unexpected end of binary carrier".to_owned(),
+            ));
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, PropagationError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, PropagationError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.read_bytes(4)?);
+        Ok(u32::from_be_bytes(bytes))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, PropagationError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_be_bytes(bytes))
+    }
+
+    fn read_string(&mut self) -> Result<String, PropagationError> {
+        let len = self.read_u32()? as usize;
+        let bytes = self.read_bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| {
+            PropagationError::SpanContextCorrupted("baggage is not valid UTF-8".to_owned())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> SpanContext {
+        let mut state = SpanState::new(
+            TraceId {
+                high: 0x0102_0304_0506_0708,
+                low: 0x090a_0b0c_0d0e_0f10,
+            },
+            0x42,
+            true,
+        );
+        state.parent_span_id = Some(0x24);
+        state.update_flags(|flags| flags.set_debug(true));
+
+        let mut context = SpanContext::new(state, Vec::new());
+        context.set_baggage_item("user", "alice");
+        context.set_baggage_item("empty", "");
+        context
+    }
+
+    fn inject(context: &SpanContext) -> Vec<u8> {
+        let mut buf = Vec::new();
+        inject_binary(context, &mut buf).unwrap();
+        buf
+    }
+
+    fn extract(mut buf: &[u8]) -> Result<Option<SpanContext>, PropagationError> {
+        extract_binary(&mut buf)
+    }
+
+    #[test]
+    fn round_trips_binary_carriers() {
+        let context = context();
+
+        let extracted = extract(&inject(&context)).unwrap().unwrap();
+        let state = extracted.state();
+        assert_eq!(state.trace_id(), context.state().trace_id());
+        assert_eq!(state.span_id(), 0x42);
+        assert_eq!(state.parent_span_id(), Some(0x24));
+        assert!(state.is_sampled());
+        assert!(state.is_debug());
+        assert_eq!(
+            extracted.baggage_items().collect::<Vec<_>>(),
+            [("empty", ""), ("user", "alice")]
+        );
+    }
+
+    #[test]
+    fn extracts_nothing_from_empty_binary_carriers() {
+        assert!(extract(&[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_binary_carriers() {
+        let buf = inject(&context());
+
+        for len in 1..buf.len() {
+            match extract(&buf[..len]) {
+                Err(PropagationError::SpanContextCorrupted(_)) => {}
+                other => panic!("extracted {:?} from {} bytes", other.map(|_| ()), len),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_baggage() {
+        let header = &inject(&SpanContext::new(SpanState::default(), Vec::new()))[..33];
+
+        // Announces more baggage items than the carrier holds.
+        let mut buf = header.to_vec();
+        buf.extend_from_slice(&u32::MAX.to_be_bytes());
+        assert!(extract(&buf).is_err());
+
+        // Announces a key longer than the carrier.
+        let mut buf = header.to_vec();
+        buf.extend_from_slice(&1u32.to_be_bytes());
+        buf.extend_from_slice(&u32::MAX.to_be_bytes());
+        assert!(extract(&buf).is_err());
+
+        // Holds a key that is not UTF-8.
+        let mut buf = header.to_vec();
+        buf.extend_from_slice(&1u32.to_be_bytes());
+        buf.extend_from_slice(&1u32.to_be_bytes());
+        buf.push(0xff);
+        buf.extend_from_slice(&0u32.to_be_bytes());
+        assert!(extract(&buf).is_err());
+    }
+}
@@ -0,0 +1,376 @@
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+
+use opentracing_rs_core::{BaggageItem, PropagationError, TextMapReader, TextMapWriter};
+
+use crate::{SpanContext, SpanState, TextMapPropagator, TraceId};
+
+const TRACEPARENT_HEADER: &str = "traceparent";
+const TRACESTATE_HEADER: &str = "tracestate";
+const BAGGAGE_HEADER: &str = "baggage";
+
+const SUPPORTED_VERSION: u8 = 0;
+const FLAG_SAMPLED: u8 = 0x01;
+
+/// Characters escaped in baggage values, as required by the W3C Baggage
+/// specification.
+const BAGGAGE_VALUE_ENCODE_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b',')
+    .add(b';')
+    .add(b'\\')
+    .add(b'%');
+
+/// Propagates span contexts with the W3C Trace Context `traceparent` and
+/// `tracestate` headers, and baggage with the W3C `baggage` header.
+///
+/// `tracestate` is not interpreted, it is carried along with the span state
+/// and written back unchanged.
+#[derive(Default)]
+pub struct W3CPropagator {}
+
+impl W3CPropagator {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl TextMapPropagator for W3CPropagator {
+    fn inject(&self, context: &SpanContext, carrier: &mut dyn TextMapWriter) {
+        let state = context.state();
+        let flags = if state.is_sampled() { FLAG_SAMPLED } else { 0 };
+
+        let traceparent = format!(
+            "{:02x}-{:016x}{:016x}-{:016x}-{:02x}",
+            SUPPORTED_VERSION, state.trace_id.high, state.trace_id.low, state.span_id, flags
+        );
+        carrier.set(TRACEPARENT_HEADER, &traceparent);
+
+        if let Some(trace_state) = &state.trace_state {
+            carrier.set(TRACESTATE_HEADER, trace_state);
+        }
+
+        let baggage = context
+            .baggage_items()
+            .map(|(key, value)| {
+                format!(
+                    "{}={}",
+                    key,
+                    utf8_percent_encode(value, BAGGAGE_VALUE_ENCODE_SET)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        if !baggage.is_empty() {
+            carrier.set(BAGGAGE_HEADER, &baggage);
+        }
+    }
+
+    fn extract(
+        &self,
+        carrier: &dyn TextMapReader,
+    ) -> Result<Option<SpanContext>, PropagationError> {
+        let mut traceparent = None;
+        let mut trace_state = None;
+        let mut baggage = None;
+
+        for key in carrier.keys() {
+            let value = match carrier.get(key) {
+                Some(value) => value,
+                None => continue,
+            };
+
+            match key.to_ascii_lowercase().as_str() {
+                TRACEPARENT_HEADER => traceparent = Some(value),
+                TRACESTATE_HEADER => trace_state = Some(value),
+                BAGGAGE_HEADER => baggage = Some(value),
+                _ => {}
+            }
+        }
+
+        let mut state = match traceparent {
+            Some(traceparent) => parse_traceparent(traceparent)?,
+            None => return Ok(None),
+        };
+
+        state.trace_state = trace_state
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_owned);
+
+        let baggage_items = match baggage {
+            Some(baggage) => parse_baggage(baggage)?,
+            None => Vec::new(),
+        };
+
+        Ok(Some(SpanContext::new(state, baggage_items)))
+    }
+}
+
+fn parse_traceparent(value: &str) -> Result<SpanState, PropagationError> {
+    let corrupted = |reason: &str| {
+        PropagationError::SpanContextCorrupted(format!(
+            "invalid {} value {:?}: {}",
+            TRACEPARENT_HEADER, value, reason
+        ))
+    };
+
+    let value = value.trim();
+    let parts: Vec<&str> = value.split('-').collect();
+    if parts.len() < 4 {
+        return Err(corrupted("expected version-traceid-parentid-flags"));
+    }
+
+    let version = parse_lower_hex(parts[0], 2).ok_or_else(|| corrupted("invalid version"))?;
+    if version == 0xff {
+        return Err(corrupted("version ff is forbidden"));
+    }
+    // Later versions may append fields, but the first four keep their layout.
+    if version == u64::from(SUPPORTED_VERSION) && parts.len() != 4 {
+        return Err(corrupted("unexpected trailing fields"));
+    }
+
+    if parts[1].len() != 32 {
+        return Err(corrupted("invalid trace id"));
+    }
+    let (high, low) = parts[1].split_at(16);
+    let trace_id = match (parse_lower_hex(high, 16), parse_lower_hex(low, 16)) {
+        (Some(high), Some(low)) => TraceId { low, high },
+        _ => return Err(corrupted("invalid trace id")),
+    };
+    if trace_id.high == 0 && trace_id.low == 0 {
+        return Err(corrupted("trace id must not be zero"));
+    }
+
+    let span_id = parse_lower_hex(parts[2], 16).ok_or_else(|| corrupted("invalid parent id"))?;
+    if span_id == 0 {
+        return Err(corrupted("parent id must not be zero"));
+    }
+
+    let flags = parse_lower_hex(parts[3], 2).ok_or_else(|| corrupted("invalid trace flags"))?;
+
+    Ok(SpanState::new(
+        trace_id,
+        span_id,
+        flags & u64::from(FLAG_SAMPLED) != 0,
+    ))
+}
+
+/// Parses exactly `len` lowercase hex digits.
+fn parse_lower_hex(s: &str, len: usize) -> Option<u64> {
+    if s.len() != len
+        || !s
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+    {
+        return None;
+    }
+    u64::from_str_radix(s, 16).ok()
+}
+
+fn parse_baggage(value: &str) -> Result<Vec<BaggageItem>, PropagationError> {
+    let corrupted = |reason: &str| {
+        PropagationError::SpanContextCorrupted(format!(
+            "invalid {} value {:?}: {}",
+            BAGGAGE_HEADER, value, reason
+        ))
+    };
+
+    let mut baggage_items = Vec::new();
+    for member in value.split(',') {
+        let member = member.trim();
+        if member.is_empty() {
+            continue;
+        }
+
+        // Metadata properties after `;` are not supported by the span context.
+        let key_value = member.split(';').next().unwrap_or_default();
+        let mut kv = key_value.splitn(2, '=');
+        let key = kv.next().unwrap_or_default().trim();
+        let value = kv
+            .next()
+            .ok_or_else(|| corrupted("member without value"))?
+            .trim();
+
+        if key.is_empty() || !key.bytes().all(is_token_char) {
+            return Err(corrupted("invalid key"));
+        }
+        let value = percent_decode_str(value)
+            .decode_utf8()
+            .map_err(|_| corrupted("value is not valid UTF-8"))?;
+
+        baggage_items.push(BaggageItem::new(key, &value));
+    }
+
+    Ok(baggage_items)
+}
+
+/// `tchar` from RFC 7230.
+fn is_token_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn carrier(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn extract(entries: &[(&str, &str)]) -> Result<Option<SpanContext>, PropagationError> {
+        W3CPropagator::new().extract(&carrier(entries))
+    }
+
+    #[test]
+    fn extracts_traceparent() {
+        let context = extract(&[("Traceparent", TRACEPARENT)]).unwrap().unwrap();
+        let state = context.state();
+        assert_eq!(
+            state.trace_id(),
+            &TraceId {
+                high: 0x4bf9_2f35_77b3_4da6,
+                low: 0xa3ce_929d_0e0e_4736,
+            }
+        );
+        assert_eq!(state.span_id(), 0x00f0_67aa_0ba9_02b7);
+        assert!(state.is_sampled());
+        assert!(state.trace_state.is_none());
+
+        let context = extract(&[(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+        )])
+        .unwrap()
+        .unwrap();
+        assert!(!context.state().is_sampled());
+    }
+
+    #[test]
+    fn extracts_future_versions() {
+        let context = extract(&[(
+            "traceparent",
+            "cc-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-what-comes-next",
+        )])
+        .unwrap()
+        .unwrap();
+        assert_eq!(context.state().span_id(), 0x00f0_67aa_0ba9_02b7);
+    }
+
+    #[test]
+    fn extracts_nothing_without_traceparent() {
+        assert!(
+            extract(&[("tracestate", "congo=t61rcWkgMzE"), ("baggage", "k=v")])
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_traceparent() {
+        for value in &[
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-00",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "0-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-1",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-zz",
+        ] {
+            match extract(&[("traceparent", value)]) {
+                Err(PropagationError::SpanContextCorrupted(_)) => {}
+                other => panic!("extracted {:?} from {:?}", other.map(|_| ()), value),
+            }
+        }
+    }
+
+    #[test]
+    fn extracts_tracestate() {
+        let context = extract(&[
+            ("traceparent", TRACEPARENT),
+            ("tracestate", " rojo=00f067aa0ba902b7,congo=t61rcWkgMzE "),
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            context.state().trace_state.as_deref(),
+            Some("rojo=00f067aa0ba902b7,congo=t61rcWkgMzE")
+        );
+
+        let context = extract(&[("traceparent", TRACEPARENT), ("tracestate", "  ")])
+            .unwrap()
+            .unwrap();
+        assert!(context.state().trace_state.is_none());
+    }
+
+    #[test]
+    fn extracts_baggage() {
+        let context = extract(&[
+            ("traceparent", TRACEPARENT),
+            (
+                "baggage",
+                "user = alice%20smith, ,region=eu;ttl=60,emoji=%F0%9F%98%80",
+            ),
+        ])
+        .unwrap()
+        .unwrap();
+        assert_eq!(context.baggage_item("user"), Some("alice smith"));
+        assert_eq!(context.baggage_item("region"), Some("eu"));
+        assert_eq!(context.baggage_item("emoji"), Some("\u{1f600}"));
+    }
+
+    #[test]
+    fn rejects_malformed_baggage() {
+        for value in &["user", "=alice", "us er=alice", "user=%ff"] {
+            match extract(&[("traceparent", TRACEPARENT), ("baggage", value)]) {
+                Err(PropagationError::SpanContextCorrupted(_)) => {}
+                other => panic!("extracted {:?} from {:?}", other.map(|_| ()), value),
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let propagator = W3CPropagator::new();
+        let mut state = SpanState::new(
+            TraceId {
+                high: 0x0102,
+                low: 0xdead_beef,
+            },
+            0x42,
+            true,
+        );
+        state.trace_state = Some("congo=t61rcWkgMzE".to_owned());
+        let mut context = SpanContext::new(state, Vec::new());
+        context.set_baggage_item("user", "alice smith");
+        context.set_baggage_item("query", "a=1;b,c%\"");
+
+        let mut carrier = HashMap::new();
+        propagator.inject(&context, &mut carrier);
+        assert_eq!(
+            carrier[TRACEPARENT_HEADER],
+            "00-000000000000010200000000deadbeef-0000000000000042-01"
+        );
+
+        let extracted = propagator.extract(&carrier).unwrap().unwrap();
+        let state = extracted.state();
+        assert_eq!(state.trace_id(), context.state().trace_id());
+        assert_eq!(state.span_id(), 0x42);
+        assert!(state.is_sampled());
+        assert_eq!(state.trace_state.as_deref(), Some("congo=t61rcWkgMzE"));
+        assert_eq!(extracted.baggage_item("user"), Some("alice smith"));
+        assert_eq!(extracted.baggage_item("query"), Some("a=1;b,c%\""));
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures::task::AtomicWaker;
+use futures::Stream;
+
+use opentracing_rs_core::SpanSink;
+
+use crate::{Span, SpanState};
+
+/// What a full queue does with a new item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DropPolicy {
+    /// Drops the new item.
+    #[default]
+    Newest,
+    /// Drops the oldest queued item to make room for the new one.
+    Oldest,
+}
+
+/// A queue that never blocks its producers, dropping items according to its
+/// policy once `capacity` items are waiting, or every item once closed.
+pub(crate) struct BoundedQueue<T> {
+    state: Mutex<QueueState<T>>,
+    capacity: usize,
+    policy: DropPolicy,
+    dropped: AtomicUsize,
+    consumer: AtomicWaker,
+}
+
+struct QueueState<T> {
+    items: VecDeque<T>,
+    closed: bool,
+}
+
+impl<T> BoundedQueue<T> {
+    pub(crate) fn new(capacity: usize, policy: DropPolicy) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(QueueState {
+                items: VecDeque::new(),
+                closed: false,
+            }),
+            capacity,
+            policy,
+            dropped: AtomicUsize::new(0),
+            consumer: AtomicWaker::new(),
+        })
+    }
+
+    pub(crate) fn push(&self, item: T) {
+        let dropped = {
+            let mut state = self.state.lock().unwrap();
+            let QueueState { items, closed } = &mut *state;
+            if *closed {
+                Some(item)
+            } else if items.len() < self.capacity {
+                items.push_back(item);
+                None
+            } else {
+                match self.policy {
+                    DropPolicy::Newest => Some(item),
+                    DropPolicy::Oldest => {
+                        let oldest = items.pop_front();
+                        items.push_back(item);
+                        oldest
+                    }
+                }
+            }
+        };
+
+        if dropped.is_some() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        // Wakes the consumer even on drops, it is likely behind.
+        self.consumer.wake();
+    }
+
+    /// Stops accepting items, the receiver ends once it got the queued ones.
+    pub(crate) fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.consumer.wake();
+    }
+
+    /// Number of items dropped because the queue was full or closed.
+    pub(crate) fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.state.lock().unwrap().items.len()
+    }
+
+    /// Streams the queued items, meant for a single consumer.
+    pub(crate) fn receiver(self: &Arc<Self>) -> QueueReceiver<T> {
+        QueueReceiver {
+            queue: self.clone(),
+        }
+    }
+}
+
+impl SpanSink<SpanState> for BoundedQueue<Span> {
+    fn submit(&self, span: Span) {
+        self.push(span);
+    }
+}
+
+pub(crate) struct QueueReceiver<T> {
+    queue: Arc<BoundedQueue<T>>,
+}
+
+impl<T> Stream for QueueReceiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        // Registering first so that a push racing with the check below still
+        // wakes this task.
+        self.queue.consumer.register(cx.waker());
+
+        let mut state = self.queue.state.lock().unwrap();
+        match state.items.pop_front() {
+            Some(item) => Poll::Ready(Some(item)),
+            None if state.closed => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{FutureExt, StreamExt};
+
+    use super::*;
+
+    fn queue(policy: DropPolicy, items: &[u32]) -> Arc<BoundedQueue<u32>> {
+        let queue = BoundedQueue::new(2, policy);
+        for &item in items {
+            queue.push(item);
+        }
+        queue
+    }
+
+    fn drain(queue: &Arc<BoundedQueue<u32>>) -> Vec<u32> {
+        let mut receiver = queue.receiver();
+        let mut items = Vec::new();
+        while let Some(Some(item)) = receiver.next().now_or_never() {
+            items.push(item);
+        }
+        items
+    }
+
+    #[test]
+    fn drops_newest_items_at_capacity() {
+        let queue = queue(DropPolicy::Newest, &[1, 2, 3, 4]);
+
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(drain(&queue), [1, 2]);
+    }
+
+    #[test]
+    fn drops_oldest_items_at_capacity() {
+        let queue = queue(DropPolicy::Oldest, &[1, 2, 3, 4]);
+
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.dropped(), 2);
+        assert_eq!(drain(&queue), [3, 4]);
+    }
+
+    #[test]
+    fn makes_room_as_items_are_received() {
+        let queue = queue(DropPolicy::Newest, &[1, 2]);
+        assert_eq!(drain(&queue), [1, 2]);
+
+        queue.push(3);
+        assert_eq!(queue.dropped(), 0);
+        assert_eq!(drain(&queue), [3]);
+    }
+
+    #[test]
+    fn receiver_waits_until_closed() {
+        let queue = queue(DropPolicy::Newest, &[]);
+        let mut receiver = queue.receiver();
+        assert_eq!(receiver.next().now_or_never(), None);
+
+        queue.close();
+        assert_eq!(receiver.next().now_or_never(), Some(None));
+    }
+
+    #[test]
+    fn drops_items_pushed_after_close() {
+        let queue = queue(DropPolicy::Newest, &[1]);
+        queue.close();
+        queue.push(2);
+
+        assert_eq!(queue.dropped(), 1);
+        let mut receiver = queue.receiver();
+        assert_eq!(receiver.next().now_or_never(), Some(Some(1)));
+        assert_eq!(receiver.next().now_or_never(), Some(None));
+    }
+}
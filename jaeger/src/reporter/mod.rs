@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+use futures::future::{self, BoxFuture, FutureExt};
+use futures::Future;
+
+use crate::{runtime, signal::CloseSignal, Span, Transport};
+
+mod tail_sampling;
+
+pub use self::tail_sampling::{
+    TailSamplingPolicy, TailSamplingReporter, TailSamplingReporterBuilder,
+};
+
+pub trait Reporter: Send {
+    fn report(&mut self, span: Span);
+    fn close(&mut self);
+
+    /// Closes the reporter, the returned future resolves once the spans
+    /// reported so far have been sent.
+    fn shutdown(&mut self) -> BoxFuture<'static, ()> {
+        self.close();
+        future::ready(()).boxed()
+    }
+}
+
+impl<R> Reporter for Box<R>
+where
+    R: Reporter + ?Sized,
+{
+    fn report(&mut self, span: Span) {
+        (**self).report(span);
+    }
+
+    fn close(&mut self) {
+        (**self).close();
+    }
+
+    fn shutdown(&mut self) -> BoxFuture<'static, ()> {
+        (**self).shutdown()
+    }
+}
+
+#[derive(Default)]
+pub struct NullReporter {}
+
+impl NullReporter {}
+
+impl Reporter for NullReporter {
+    fn report(&mut self, _span: Span) {}
+    fn close(&mut self) {}
+}
+
+#[derive(Default)]
+pub struct LoggingReporter {
+    // TODO logger
+}
+
+impl LoggingReporter {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Reporter for LoggingReporter {
+    fn report(&mut self, span: Span) {
+        println!("reporting span {:?}", span);
+    }
+
+    fn close(&mut self) {}
+}
+
+#[derive(Default)]
+pub struct RemoteReporter<T> {
+    sender: T,
+    closed: CloseSignal,
+}
+
+impl<T> RemoteReporter<T>
+where
+    T: Transport + Clone,
+{
+    pub fn new(sender: T) -> Self {
+        Self {
+            sender,
+            closed: CloseSignal::default(),
+        }
+    }
+
+    /// Flushes the transport every `duration`, until the reporter shuts down.
+    pub fn interval_flush(&self, duration: Duration) -> impl Future<Output = ()> {
+        let mut sender = self.sender.clone();
+        self.closed.guard(async move {
+            loop {
+                runtime::sleep(duration).await;
+                sender.flush();
+            }
+        })
+    }
+}
+
+impl<T> Reporter for RemoteReporter<T>
+where
+    T: Transport,
+{
+    fn report(&mut self, span: Span) {
+        self.sender.append(span);
+    }
+
+    fn close(&mut self) {
+        self.sender.flush();
+    }
+
+    fn shutdown(&mut self) -> BoxFuture<'static, ()> {
+        self.closed.close();
+        self.sender.shutdown()
+    }
+}
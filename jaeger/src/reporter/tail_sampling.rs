@@ -0,0 +1,556 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::future::BoxFuture;
+use futures::Future;
+use opentracing_rs_core::TagValue;
+
+use crate::{runtime, signal::CloseSignal, tag, Reporter, Span, TraceId};
+
+const DEFAULT_TRACE_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_MAX_TRACES: usize = 10_000;
+const DEFAULT_MAX_SPANS: usize = 100_000;
+
+/// Decides whether a buffered trace is worth keeping.
+#[derive(Debug, Clone)]
+pub enum TailSamplingPolicy {
+    /// Keeps traces with a span tagged `error=true`.
+    Error,
+    /// Keeps traces whose buffered spans cover at least this duration.
+    Latency(Duration),
+    /// Keeps traces with a span of this operation.
+    Operation(String),
+}
+
+impl TailSamplingPolicy {
+    fn matches(&self, spans: &[Span]) -> bool {
+        match self {
+            TailSamplingPolicy::Error => spans.iter().any(|span| {
+                span.tags().iter().any(|tag| {
+                    tag.name() == tag::ERROR_TAG_KEY && *tag.value() == TagValue::Bool(true)
+                })
+            }),
+            TailSamplingPolicy::Latency(threshold) => latency(spans) >= *threshold,
+            TailSamplingPolicy::Operation(operation) => {
+                spans.iter().any(|span| span.operation_name() == operation)
+            }
+        }
+    }
+}
+
+/// Time from the earliest start to the latest finish of `spans`.
+fn latency(spans: &[Span]) -> Duration {
+    let start = spans.iter().map(Span::start_time).min();
+    let finish = spans
+        .iter()
+        .map(|span| span.start_time() + span.duration())
+        .max();
+
+    match (start, finish) {
+        (Some(start), Some(finish)) => finish
+            .duration_since(start)
+            .unwrap_or_else(|_| Duration::from_secs(0)),
+        _ => Duration::from_secs(0),
+    }
+}
+
+pub struct TailSamplingReporterBuilder<R> {
+    reporter: R,
+    policies: Vec<TailSamplingPolicy>,
+    trace_timeout: Duration,
+    max_traces: usize,
+    max_spans: usize,
+}
+
+impl<R> TailSamplingReporterBuilder<R>
+where
+    R: Reporter + 'static,
+{
+    /// Keeps traces matching `policy`, in addition to the other policies.
+    pub fn policy(mut self, policy: TailSamplingPolicy) -> Self {
+        self.policies.push(policy);
+        self
+    }
+
+    /// Decides on traces whose local root has not finished this long after
+    /// their first span got reported, defaults to 30 seconds.
+    pub fn trace_timeout(mut self, trace_timeout: Duration) -> Self {
+        self.trace_timeout = trace_timeout;
+        self
+    }
+
+    /// Limits the number of buffered traces, defaults to 10,000.
+    pub fn max_traces(mut self, max_traces: usize) -> Self {
+        self.max_traces = max_traces;
+        self
+    }
+
+    /// Limits the number of buffered spans across all traces, defaults to
+    /// 100,000.
+    pub fn max_spans(mut self, max_spans: usize) -> Self {
+        self.max_spans = max_spans;
+        self
+    }
+
+    /// Builds the reporter, and a future deciding on timed out traces that
+    /// needs to be spawned.
+    pub fn build_and_serve(self) -> (TailSamplingReporter<R>, impl Future<Output = ()>) {
+        let check_interval = (self.trace_timeout / 10).max(Duration::from_millis(100));
+        let reporter = TailSamplingReporter {
+            buffer: Arc::new(Mutex::new(TraceBuffer {
+                reporter: self.reporter,
+                policies: self.policies,
+                trace_timeout: self.trace_timeout,
+                max_traces: self.max_traces,
+                max_spans: self.max_spans,
+                traces: HashMap::new(),
+                arrival: BTreeMap::new(),
+                next_arrival: 0,
+                span_count: 0,
+                decisions: HashMap::new(),
+                decision_order: VecDeque::new(),
+            })),
+            counters: Arc::new(Counters::default()),
+            closed: CloseSignal::default(),
+        };
+
+        let buffer = reporter.buffer.clone();
+        let counters = reporter.counters.clone();
+        let serve = reporter.closed.guard(async move {
+            loop {
+                runtime::sleep(check_interval).await;
+                buffer.lock().unwrap().decide_expired(&counters);
+            }
+        });
+
+        (reporter, serve)
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    kept_traces: AtomicUsize,
+    dropped_traces: AtomicUsize,
+    expired_traces: AtomicUsize,
+    evicted_traces: AtomicUsize,
+}
+
+/// Buffers the finished spans of each local trace until its local root
+/// finishes or it times out, then reports the whole trace to the wrapped
+/// reporter if any policy matches it, or drops it.
+///
+/// Kept traces are marked sampled, so every span of the trace should reach
+/// this reporter: pair it with a sampler sampling everything. Spans finishing
+/// after the decision on their trace follow it.
+#[derive(Clone)]
+pub struct TailSamplingReporter<R> {
+    buffer: Arc<Mutex<TraceBuffer<R>>>,
+    counters: Arc<Counters>,
+    closed: CloseSignal,
+}
+
+impl<R> TailSamplingReporter<R>
+where
+    R: Reporter + 'static,
+{
+    pub fn builder(reporter: R) -> TailSamplingReporterBuilder<R> {
+        TailSamplingReporterBuilder {
+            reporter,
+            policies: Vec::new(),
+            trace_timeout: DEFAULT_TRACE_TIMEOUT,
+            max_traces: DEFAULT_MAX_TRACES,
+            max_spans: DEFAULT_MAX_SPANS,
+        }
+    }
+
+    /// Number of traces reported to the wrapped reporter.
+    pub fn kept_traces(&self) -> usize {
+        self.counters.kept_traces.load(Ordering::Relaxed)
+    }
+
+    /// Number of traces no policy matched.
+    pub fn dropped_traces(&self) -> usize {
+        self.counters.dropped_traces.load(Ordering::Relaxed)
+    }
+
+    /// Number of traces decided on because their local root did not finish
+    /// in time.
+    pub fn expired_traces(&self) -> usize {
+        self.counters.expired_traces.load(Ordering::Relaxed)
+    }
+
+    /// Number of traces decided on early to stay within the memory limits.
+    pub fn evicted_traces(&self) -> usize {
+        self.counters.evicted_traces.load(Ordering::Relaxed)
+    }
+
+    /// Number of spans currently buffered.
+    pub fn buffered_spans(&self) -> usize {
+        self.buffer.lock().unwrap().span_count
+    }
+}
+
+impl<R> Reporter for TailSamplingReporter<R>
+where
+    R: Reporter + 'static,
+{
+    fn report(&mut self, span: Span) {
+        self.buffer.lock().unwrap().append(span, &self.counters);
+    }
+
+    /// Decides on every buffered trace before closing the wrapped reporter.
+    fn close(&mut self) {
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.decide_all(&self.counters);
+        buffer.reporter.close();
+    }
+
+    fn shutdown(&mut self) -> BoxFuture<'static, ()> {
+        self.closed.close();
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.decide_all(&self.counters);
+        buffer.reporter.shutdown()
+    }
+}
+
+struct PendingTrace {
+    arrival: u64,
+    first_reported: Instant,
+    spans: Vec<Span>,
+}
+
+struct TraceBuffer<R> {
+    reporter: R,
+    policies: Vec<TailSamplingPolicy>,
+    trace_timeout: Duration,
+    max_traces: usize,
+    max_spans: usize,
+    traces: HashMap<TraceId, PendingTrace>,
+    /// Buffered traces by order of arrival, which is also their order of
+    /// expiry.
+    arrival: BTreeMap<u64, TraceId>,
+    next_arrival: u64,
+    span_count: usize,
+    /// Recent decisions, applied to the spans finishing after them.
+    decisions: HashMap<TraceId, bool>,
+    decision_order: VecDeque<TraceId>,
+}
+
+impl<R> TraceBuffer<R>
+where
+    R: Reporter,
+{
+    fn append(&mut self, span: Span, counters: &Counters) {
+        let state = span.context().state();
+        let trace_id = state.trace_id().clone();
+        let is_local_root = state.is_local_root();
+
+        if let Some(&keep) = self.decisions.get(&trace_id) {
+            if keep {
+                state.update_flags(|flags| flags.set_sampled(true));
+                self.reporter.report(span);
+            }
+            return;
+        }
+
+        let next_arrival = &mut self.next_arrival;
+        let arrival = &mut self.arrival;
+        self.traces
+            .entry(trace_id.clone())
+            .or_insert_with(|| {
+                let id = *next_arrival;
+                *next_arrival += 1;
+                arrival.insert(id, trace_id.clone());
+                PendingTrace {
+                    arrival: id,
+                    first_reported: Instant::now(),
+                    spans: Vec::new(),
+                }
+            })
+            .spans
+            .push(span);
+        self.span_count += 1;
+
+        if is_local_root {
+            self.decide(&trace_id, counters);
+        }
+
+        while self.traces.len() > self.max_traces || self.span_count > self.max_spans {
+            let oldest = match self.oldest() {
+                Some(oldest) => oldest,
+                None => break,
+            };
+            self.decide(&oldest, counters);
+            counters.evicted_traces.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn oldest(&self) -> Option<TraceId> {
+        self.arrival.values().next().cloned()
+    }
+
+    fn decide_expired(&mut self, counters: &Counters) {
+        while let Some(trace_id) = self.oldest() {
+            if self.traces[&trace_id].first_reported.elapsed() < self.trace_timeout {
+                break;
+            }
+            self.decide(&trace_id, counters);
+            counters.expired_traces.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn decide_all(&mut self, counters: &Counters) {
+        while let Some(trace_id) = self.oldest() {
+            self.decide(&trace_id, counters);
+        }
+    }
+
+    fn decide(&mut self, trace_id: &TraceId, counters: &Counters) {
+        let trace = match self.traces.remove(trace_id) {
+            Some(trace) => trace,
+            None => return,
+        };
+        self.arrival.remove(&trace.arrival);
+        self.span_count -= trace.spans.len();
+
+        let keep = self
+            .policies
+            .iter()
+            .any(|policy| policy.matches(&trace.spans));
+        if keep {
+            counters.kept_traces.fetch_add(1, Ordering::Relaxed);
+            for span in trace.spans {
+                span.context()
+                    .state()
+                    .update_flags(|flags| flags.set_sampled(true));
+                self.reporter.report(span);
+            }
+        } else {
+            counters.dropped_traces.fetch_add(1, Ordering::Relaxed);
+        }
+
+        self.decisions.insert(trace_id.clone(), keep);
+        self.decision_order.push_back(trace_id.clone());
+        while self.decision_order.len() > self.max_traces {
+            if let Some(trace_id) = self.decision_order.pop_front() {
+                self.decisions.remove(&trace_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use opentracing_rs_core::{SpanBuilder as _, SpanReference, Tag};
+
+    use super::*;
+    use crate::testing::{RecordingReporter, SpanRecorder};
+
+    fn tail_reporter(
+        policies: Vec<TailSamplingPolicy>,
+    ) -> (TailSamplingReporter<RecordingReporter>, RecordingReporter) {
+        let recorded = RecordingReporter::default();
+        let builder = policies.into_iter().fold(
+            TailSamplingReporter::builder(recorded.clone()),
+            TailSamplingReporterBuilder::policy,
+        );
+        let (reporter, _serve) = builder.build_and_serve();
+        (reporter, recorded)
+    }
+
+    /// Finishes `child` then `root` of one trace, reporting both.
+    fn report_trace(
+        reporter: &mut TailSamplingReporter<RecordingReporter>,
+        root: &str,
+        child: &str,
+        child_tags: Vec<Tag>,
+    ) {
+        let spans = SpanRecorder::default();
+        let mut root = spans.span(root).start();
+        let child = child_tags.into_iter().fold(
+            spans
+                .span(child)
+                .add_reference(SpanReference::ChildOf(root.context())),
+            |builder, tag| builder.tag(tag),
+        );
+        drop(child.start());
+        root.finish();
+
+        for span in spans.finished() {
+            reporter.report(span);
+        }
+    }
+
+    #[test]
+    fn keeps_traces_with_errors() {
+        let (mut reporter, recorded) = tail_reporter(vec![TailSamplingPolicy::Error]);
+
+        report_trace(
+            &mut reporter,
+            "root",
+            "child",
+            vec![Tag::new("error", true)],
+        );
+
+        assert_eq!(recorded.operations(), ["child", "root"]);
+        assert!(recorded
+            .spans
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|span| span.context().state().is_sampled()));
+        assert_eq!((reporter.kept_traces(), reporter.dropped_traces()), (1, 0));
+        assert_eq!(reporter.buffered_spans(), 0);
+    }
+
+    #[test]
+    fn drops_traces_matching_no_policy() {
+        let (mut reporter, recorded) = tail_reporter(vec![
+            TailSamplingPolicy::Error,
+            TailSamplingPolicy::Operation("checkout".to_owned()),
+            TailSamplingPolicy::Latency(Duration::from_secs(60)),
+        ]);
+
+        report_trace(
+            &mut reporter,
+            "root",
+            "child",
+            vec![Tag::new("error", false)],
+        );
+
+        assert!(recorded.operations().is_empty());
+        assert_eq!((reporter.kept_traces(), reporter.dropped_traces()), (0, 1));
+        assert_eq!(reporter.buffered_spans(), 0);
+    }
+
+    #[test]
+    fn keeps_traces_by_operation_and_latency() {
+        let (mut reporter, recorded) =
+            tail_reporter(vec![TailSamplingPolicy::Operation("checkout".to_owned())]);
+        report_trace(&mut reporter, "root", "checkout", Vec::new());
+        assert_eq!(recorded.operations(), ["checkout", "root"]);
+
+        let (mut reporter, recorded) =
+            tail_reporter(vec![TailSamplingPolicy::Latency(Duration::from_secs(1))]);
+        let spans = SpanRecorder::default();
+        drop(
+            spans
+                .span("slow")
+                .start_time(SystemTime::now() - Duration::from_secs(2))
+                .start(),
+        );
+        drop(spans.span("fast").start());
+        for span in spans.finished() {
+            reporter.report(span);
+        }
+        assert_eq!(recorded.operations(), ["slow"]);
+        assert_eq!((reporter.kept_traces(), reporter.dropped_traces()), (1, 1));
+    }
+
+    #[test]
+    fn applies_decisions_to_late_spans() {
+        let (mut reporter, recorded) = tail_reporter(vec![TailSamplingPolicy::Error]);
+        let spans = SpanRecorder::default();
+
+        let kept = spans.span("kept").tag(Tag::new("error", true)).start();
+        let kept_late = spans
+            .span("kept-late")
+            .add_reference(SpanReference::ChildOf(kept.context()))
+            .start();
+        let dropped = spans.span("dropped").start();
+        let dropped_late = spans
+            .span("dropped-late")
+            .add_reference(SpanReference::ChildOf(dropped.context()))
+            .start();
+
+        drop(kept);
+        drop(dropped);
+        for span in spans.finished() {
+            reporter.report(span);
+        }
+        drop(kept_late);
+        drop(dropped_late);
+        for span in spans.finished() {
+            reporter.report(span);
+        }
+
+        assert_eq!(recorded.operations(), ["kept", "kept-late"]);
+        assert!(recorded.spans.lock().unwrap()[1]
+            .context()
+            .state()
+            .is_sampled());
+    }
+
+    #[test]
+    fn decides_early_past_limits() {
+        let recorded = RecordingReporter::default();
+        let (mut reporter, _serve) = TailSamplingReporter::builder(recorded.clone())
+            .policy(TailSamplingPolicy::Operation("keep".to_owned()))
+            .max_traces(2)
+            .build_and_serve();
+        let spans = SpanRecorder::default();
+
+        // Children of unfinished roots, which stay buffered.
+        let roots: Vec<_> = (0..3).map(|_| spans.span("root").start()).collect();
+        for (root, operation) in roots.iter().zip(&["keep", "drop", "keep"]) {
+            drop(
+                spans
+                    .span(operation)
+                    .add_reference(SpanReference::ChildOf(root.context()))
+                    .start(),
+            );
+        }
+        for span in spans.finished() {
+            reporter.report(span);
+        }
+
+        assert_eq!(recorded.operations(), ["keep"]);
+        assert_eq!(reporter.evicted_traces(), 1);
+        assert_eq!(reporter.buffered_spans(), 2);
+
+        reporter.close();
+        assert_eq!(recorded.operations(), ["keep", "keep"]);
+        assert_eq!((reporter.kept_traces(), reporter.dropped_traces()), (2, 1));
+        assert_eq!(reporter.buffered_spans(), 0);
+        drop(roots);
+    }
+
+    #[tokio::test]
+    async fn decides_on_timed_out_traces() {
+        let recorded = RecordingReporter::default();
+        let (mut reporter, serve) = TailSamplingReporter::builder(recorded.clone())
+            .policy(TailSamplingPolicy::Operation("child".to_owned()))
+            .trace_timeout(Duration::from_millis(50))
+            .build_and_serve();
+        tokio::spawn(serve);
+        let spans = SpanRecorder::default();
+
+        let root = spans.span("root").start();
+        drop(
+            spans
+                .span("child")
+                .add_reference(SpanReference::ChildOf(root.context()))
+                .start(),
+        );
+        for span in spans.finished() {
+            reporter.report(span);
+        }
+        assert_eq!(reporter.buffered_spans(), 1);
+
+        let started = Instant::now();
+        while reporter.expired_traces() == 0 {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "trace never expired"
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(recorded.operations(), ["child"]);
+        reporter.shutdown().await;
+        drop(root);
+    }
+}
@@ -0,0 +1,100 @@
+use std::future::Future;
+use std::io;
+use std::net::{self, SocketAddr};
+use std::time::Duration;
+
+#[cfg(not(any(feature = "tokio", feature = "async-std")))]
+compile_error!("one of the `tokio` or `async-std` features must be enabled");
+
+#[cfg(feature = "tokio")]
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(future);
+}
+
+#[cfg(all(feature = "async-std", not(feature = "tokio")))]
+pub(crate) fn spawn<F>(future: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    async_std::task::spawn(future);
+}
+
+#[cfg(feature = "tokio")]
+pub(crate) async fn sleep(duration: Duration) {
+    tokio::time::sleep(duration).await;
+}
+
+#[cfg(all(feature = "async-std", not(feature = "tokio")))]
+pub(crate) async fn sleep(duration: Duration) {
+    async_std::task::sleep(duration).await;
+}
+
+/// Resolves to `None` if `future` does not complete within `duration`.
+#[cfg(feature = "tokio")]
+pub(crate) async fn timeout<F>(duration: Duration, future: F) -> Option<F::Output>
+where
+    F: Future,
+{
+    tokio::time::timeout(duration, future).await.ok()
+}
+
+/// Resolves to `None` if `future` does not complete within `duration`.
+#[cfg(all(feature = "async-std", not(feature = "tokio")))]
+pub(crate) async fn timeout<F>(duration: Duration, future: F) -> Option<F::Output>
+where
+    F: Future,
+{
+    async_std::future::timeout(duration, future).await.ok()
+}
+
+/// Runs `future` to completion on a new single threaded runtime, blocking
+/// the current thread.
+#[cfg(feature = "tokio")]
+pub(crate) fn block_on<F>(future: F) -> io::Result<F::Output>
+where
+    F: Future,
+{
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    Ok(runtime.block_on(future))
+}
+
+/// Runs `future` to completion, blocking the current thread.
+#[cfg(all(feature = "async-std", not(feature = "tokio")))]
+pub(crate) fn block_on<F>(future: F) -> io::Result<F::Output>
+where
+    F: Future,
+{
+    Ok(async_std::task::block_on(future))
+}
+
+pub(crate) struct UdpSocket {
+    #[cfg(feature = "tokio")]
+    socket: tokio::net::UdpSocket,
+    #[cfg(all(feature = "async-std", not(feature = "tokio")))]
+    socket: async_std::net::UdpSocket,
+}
+
+impl UdpSocket {
+    /// Registers a socket bound outside of the runtime, which must be called
+    /// from within it.
+    pub(crate) fn from_std(socket: net::UdpSocket) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+
+        #[cfg(feature = "tokio")]
+        let socket = tokio::net::UdpSocket::from_std(socket)?;
+        #[cfg(all(feature = "async-std", not(feature = "tokio")))]
+        let socket = async_std::net::UdpSocket::from(socket);
+
+        Ok(Self { socket })
+    }
+
+    pub(crate) async fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        self.socket.send_to(buf, addr).await
+    }
+}
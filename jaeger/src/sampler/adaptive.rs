@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use opentracing_rs_core::Tag;
+
+use super::{rate_limiting::check_rate_limit, ProbabilisticSampler, Sampler};
+use crate::{Error, Result, TraceId};
+
+/// Samples every operation probabilistically, adjusting its sampling rate so
+/// that about `target_traces_per_second` of its traces get sampled.
+///
+/// Every adjustment interval, the root spans started for an operation over
+/// the interval are folded into an exponentially smoothed throughput, and the
+/// sampling rate becomes the target divided by it, clamped between the
+/// minimum and maximum rates.
+pub struct AdaptiveSampler {
+    target_traces_per_second: f64,
+    min_sampling_rate: f64,
+    max_sampling_rate: f64,
+    smoothing_factor: f64,
+    adjustment_interval: Duration,
+    max_operations: usize,
+    default_sampler: ProbabilisticSampler,
+    operations: Mutex<HashMap<String, OperationState>>,
+}
+
+struct OperationState {
+    sampler: ProbabilisticSampler,
+    traces_per_second: Option<f64>,
+    window_start: Instant,
+    window_traces: u64,
+}
+
+/// Current sampling state of an operation.
+#[derive(Clone, Copy, Debug)]
+pub struct OperationRate {
+    sampling_rate: f64,
+    traces_per_second: Option<f64>,
+}
+
+impl OperationRate {
+    pub fn sampling_rate(&self) -> f64 {
+        self.sampling_rate
+    }
+
+    /// Smoothed throughput of root spans, `None` until the first adjustment.
+    pub fn traces_per_second(&self) -> Option<f64> {
+        self.traces_per_second
+    }
+}
+
+impl AdaptiveSampler {
+    pub fn builder(target_traces_per_second: f64) -> AdaptiveSamplerBuilder {
+        AdaptiveSamplerBuilder::new(target_traces_per_second)
+    }
+
+    /// Returns the sampling state of every tracked operation.
+    pub fn operation_rates(&self) -> HashMap<String, OperationRate> {
+        self.operations
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(operation, state)| {
+                let rate = OperationRate {
+                    sampling_rate: state.sampler.sampling_rate(),
+                    traces_per_second: state.traces_per_second,
+                };
+                (operation.clone(), rate)
+            })
+            .collect()
+    }
+
+    fn adjust(&self, state: &mut OperationState, now: Instant) {
+        let elapsed = now.duration_since(state.window_start).as_secs_f64();
+        let observed = state.window_traces as f64 / elapsed;
+
+        let traces_per_second = match state.traces_per_second {
+            Some(smoothed) => {
+                self.smoothing_factor * observed + (1.0 - self.smoothing_factor) * smoothed
+            }
+            None => observed,
+        };
+
+        let sampling_rate = if traces_per_second > 0.0 {
+            self.target_traces_per_second / traces_per_second
+        } else {
+            self.max_sampling_rate
+        };
+        let sampling_rate = sampling_rate
+            .max(self.min_sampling_rate)
+            .min(self.max_sampling_rate);
+
+        if sampling_rate != state.sampler.sampling_rate() {
+            state.sampler = ProbabilisticSampler::clamped(sampling_rate);
+        }
+        state.traces_per_second = Some(traces_per_second);
+        state.window_start = now;
+        state.window_traces = 0;
+    }
+}
+
+impl Sampler for AdaptiveSampler {
+    fn is_sampled(&self, trace_id: &TraceId, operation: &str) -> (bool, Vec<Tag>) {
+        self.is_sampled_at(trace_id, operation, Instant::now())
+    }
+}
+
+impl AdaptiveSampler {
+    fn is_sampled_at(&self, trace_id: &TraceId, operation: &str, now: Instant) -> (bool, Vec<Tag>) {
+        let mut operations = self.operations.lock().unwrap();
+
+        if !operations.contains_key(operation) {
+            if operations.len() >= self.max_operations {
+                return self.default_sampler.is_sampled(trace_id, operation);
+            }
+
+            let state = OperationState {
+                sampler: ProbabilisticSampler::clamped(self.max_sampling_rate),
+                traces_per_second: None,
+                window_start: now,
+                window_traces: 0,
+            };
+            operations.insert(operation.to_owned(), state);
+        }
+
+        let state = operations.get_mut(operation).unwrap();
+        state.window_traces += 1;
+        if now.duration_since(state.window_start) >= self.adjustment_interval {
+            self.adjust(state, now);
+        }
+
+        state.sampler.is_sampled(trace_id, operation)
+    }
+}
+
+pub struct AdaptiveSamplerBuilder {
+    target_traces_per_second: f64,
+    min_sampling_rate: f64,
+    max_sampling_rate: f64,
+    smoothing_factor: f64,
+    adjustment_interval: Duration,
+    max_operations: usize,
+}
+
+impl AdaptiveSamplerBuilder {
+    fn new(target_traces_per_second: f64) -> Self {
+        Self {
+            target_traces_per_second,
+            min_sampling_rate: 0.00001,
+            max_sampling_rate: 1.0,
+            smoothing_factor: 0.3,
+            adjustment_interval: Duration::from_secs(5),
+            max_operations: 2000,
+        }
+    }
+
+    /// Bounds the sampling rate of every operation, `[0.00001, 1.0]` by
+    /// default.
+    pub fn sampling_rate_bounds(mut self, min: f64, max: f64) -> Self {
+        self.min_sampling_rate = min;
+        self.max_sampling_rate = max;
+        self
+    }
+
+    /// Sets the weight of the latest interval in the smoothed throughput,
+    /// 0.3 by default. `1.0` disables smoothing.
+    pub fn smoothing_factor(mut self, smoothing_factor: f64) -> Self {
+        self.smoothing_factor = smoothing_factor;
+        self
+    }
+
+    /// Sets how often sampling rates are recomputed, 5 seconds by default.
+    pub fn adjustment_interval(mut self, adjustment_interval: Duration) -> Self {
+        self.adjustment_interval = adjustment_interval;
+        self
+    }
+
+    /// Caps the tracked operations, 2000 by default. Operations past the cap
+    /// are sampled at the minimum rate.
+    pub fn max_operations(mut self, max_operations: usize) -> Self {
+        self.max_operations = max_operations;
+        self
+    }
+
+    pub fn build(self) -> Result<AdaptiveSampler> {
+        check_rate_limit(self.target_traces_per_second)?;
+        ProbabilisticSampler::new(self.min_sampling_rate)?;
+        ProbabilisticSampler::new(self.max_sampling_rate)?;
+        if self.min_sampling_rate > self.max_sampling_rate {
+            return Err(Error::InvalidConfig(format!(
+                "minimum sampling rate {} exceeds maximum {}",
+                self.min_sampling_rate, self.max_sampling_rate
+            )));
+        }
+        if !(self.smoothing_factor > 0.0 && self.smoothing_factor <= 1.0) {
+            return Err(Error::InvalidConfig(format!(
+                "smoothing factor must be in (0.0, 1.0], received {}",
+                self.smoothing_factor
+            )));
+        }
+        if self.adjustment_interval == Duration::from_secs(0) {
+            return Err(Error::InvalidConfig(
+                "adjustment interval must not be zero".to_owned(),
+            ));
+        }
+
+        Ok(AdaptiveSampler {
+            target_traces_per_second: self.target_traces_per_second,
+            min_sampling_rate: self.min_sampling_rate,
+            max_sampling_rate: self.max_sampling_rate,
+            smoothing_factor: self.smoothing_factor,
+            adjustment_interval: self.adjustment_interval,
+            max_operations: self.max_operations,
+            default_sampler: ProbabilisticSampler::clamped(self.min_sampling_rate),
+            operations: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentracing_rs_core::TagValue;
+
+    use super::*;
+    use crate::tag;
+
+    const INTERVAL: Duration = Duration::from_secs(1);
+
+    fn sampler(builder: AdaptiveSamplerBuilder) -> AdaptiveSampler {
+        builder.adjustment_interval(INTERVAL).build().unwrap()
+    }
+
+    /// Starts `traces` more root spans of `operation` in the interval
+    /// beginning at `start`, the last one ending it.
+    fn run_interval(sampler: &AdaptiveSampler, operation: &str, start: Instant, traces: u64) {
+        for _ in 1..traces {
+            sampler.is_sampled_at(&TraceId::new(), operation, start);
+        }
+        sampler.is_sampled_at(&TraceId::new(), operation, start + INTERVAL);
+    }
+
+    fn tag_value<'a>(tags: &'a [Tag], name: &str) -> Option<&'a TagValue> {
+        tags.iter().find(|tag| tag.name() == name).map(Tag::value)
+    }
+
+    fn rate(sampler: &AdaptiveSampler, operation: &str) -> OperationRate {
+        sampler.operation_rates()[operation]
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-9,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn converges_toward_target_throughput() {
+        let sampler = sampler(AdaptiveSampler::builder(10.0).smoothing_factor(1.0));
+        let start = Instant::now();
+
+        sampler.is_sampled_at(&TraceId::new(), "op", start);
+        assert_close(rate(&sampler, "op").sampling_rate(), 1.0);
+        assert_eq!(rate(&sampler, "op").traces_per_second(), None);
+
+        // With the first span, 100 traces over the interval.
+        run_interval(&sampler, "op", start, 99);
+        let op = rate(&sampler, "op");
+        assert_close(op.traces_per_second().unwrap(), 100.0);
+        assert_close(op.sampling_rate(), 0.1);
+
+        run_interval(&sampler, "op", start + INTERVAL, 40);
+        assert_close(rate(&sampler, "op").sampling_rate(), 0.25);
+    }
+
+    #[test]
+    fn clamps_to_sampling_rate_bounds() {
+        let sampler = sampler(
+            AdaptiveSampler::builder(10.0)
+                .smoothing_factor(1.0)
+                .sampling_rate_bounds(0.2, 0.5),
+        );
+        let start = Instant::now();
+
+        sampler.is_sampled_at(&TraceId::new(), "op", start);
+        assert_close(rate(&sampler, "op").sampling_rate(), 0.5);
+
+        run_interval(&sampler, "op", start, 1000);
+        assert_close(rate(&sampler, "op").sampling_rate(), 0.2);
+
+        run_interval(&sampler, "op", start + INTERVAL, 1);
+        assert_close(rate(&sampler, "op").sampling_rate(), 0.5);
+    }
+
+    #[test]
+    fn smooths_throughput() {
+        let sampler = sampler(AdaptiveSampler::builder(10.0).smoothing_factor(0.5));
+        let start = Instant::now();
+
+        sampler.is_sampled_at(&TraceId::new(), "op", start);
+        // With the first span, 100 traces over the interval.
+        run_interval(&sampler, "op", start, 99);
+        assert_close(rate(&sampler, "op").traces_per_second().unwrap(), 100.0);
+
+        run_interval(&sampler, "op", start + INTERVAL, 20);
+        let op = rate(&sampler, "op");
+        assert_close(op.traces_per_second().unwrap(), 60.0);
+        assert_close(op.sampling_rate(), 10.0 / 60.0);
+    }
+
+    #[test]
+    fn samples_operations_past_the_cap_at_the_minimum_rate() {
+        let sampler = sampler(
+            AdaptiveSampler::builder(10.0)
+                .sampling_rate_bounds(0.1, 1.0)
+                .max_operations(1),
+        );
+        let trace_id = TraceId {
+            high: 0,
+            low: u64::MAX / 2,
+        };
+        let start = Instant::now();
+
+        let (tracked, _) = sampler.is_sampled_at(&trace_id, "tracked", start);
+        let (untracked, tags) = sampler.is_sampled_at(&trace_id, "untracked", start);
+
+        assert!(tracked);
+        assert!(!untracked);
+        assert_eq!(
+            tag_value(&tags, tag::SAMPLER_PARAM_TAG_KEY),
+            Some(&TagValue::Double(0.1))
+        );
+        let rates = sampler.operation_rates();
+        assert_eq!(rates.len(), 1);
+        assert!(rates.contains_key("tracked"));
+    }
+
+    #[test]
+    fn reports_every_tracked_operation() {
+        let sampler = sampler(AdaptiveSampler::builder(10.0).smoothing_factor(1.0));
+        let start = Instant::now();
+
+        sampler.is_sampled_at(&TraceId::new(), "busy", start);
+        sampler.is_sampled_at(&TraceId::new(), "idle", start);
+        run_interval(&sampler, "busy", start, 49);
+
+        let rates = sampler.operation_rates();
+        assert_eq!(rates.len(), 2);
+        assert_close(rates["busy"].sampling_rate(), 0.2);
+        assert_close(rates["busy"].traces_per_second().unwrap(), 50.0);
+        assert_close(rates["idle"].sampling_rate(), 1.0);
+        assert_eq!(rates["idle"].traces_per_second(), None);
+
+        let (_, tags) = sampler.is_sampled_at(&TraceId::new(), "busy", start + INTERVAL);
+        assert_eq!(
+            tag_value(&tags, tag::SAMPLER_TYPE_TAG_KEY),
+            Some(&TagValue::String(
+                tag::SAMPLER_TYPE_PROBABILISTIC.to_owned()
+            ))
+        );
+    }
+}
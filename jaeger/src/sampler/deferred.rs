@@ -0,0 +1,228 @@
+use opentracing_rs_core::Tag;
+
+use super::{Sampler, SamplingDecision};
+use crate::{tag, SpanState, TraceId};
+
+/// Matches spans carrying a tag, optionally restricted to an operation.
+pub struct TagRule {
+    operation: Option<String>,
+    tag: Tag,
+    sample: bool,
+}
+
+impl TagRule {
+    /// Samples traces with a span tagged with `tag`.
+    pub fn sample(tag: Tag) -> Self {
+        Self {
+            operation: None,
+            tag,
+            sample: true,
+        }
+    }
+
+    /// Does not sample traces with a span tagged with `tag`.
+    pub fn reject(tag: Tag) -> Self {
+        Self {
+            operation: None,
+            tag,
+            sample: false,
+        }
+    }
+
+    /// Restricts the rule to spans of `operation`.
+    pub fn operation<N>(mut self, operation: N) -> Self
+    where
+        N: Into<String>,
+    {
+        self.operation = Some(operation.into());
+        self
+    }
+
+    fn matches(&self, operation: &str, tag: &Tag) -> bool {
+        self.operation.as_ref().map_or(true, |op| op == operation)
+            && self.tag.name() == tag.name()
+            && self.tag.value() == tag.value()
+    }
+}
+
+/// Defers the sampling decision of new traces until one of their spans gets
+/// a tag matching a rule.
+///
+/// Rules are checked in order against the tags a span starts with and every
+/// tag set on it later, the first match decides for the whole local trace.
+/// If the span that started the local trace, a new root or the child of a
+/// context whose caller deferred sampling, finishes before any match, the
+/// fallback sampler decides. Spans that finished before the trace got
+/// sampled may be missing from it.
+pub struct DeferredSampler {
+    rules: Vec<TagRule>,
+    fallback: Box<dyn Sampler>,
+}
+
+impl DeferredSampler {
+    pub fn new<S>(fallback: S) -> Self
+    where
+        S: Sampler + 'static,
+    {
+        Self {
+            rules: Vec::new(),
+            fallback: Box::new(fallback),
+        }
+    }
+
+    pub fn rule(mut self, rule: TagRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    fn decide(&self, operation: &str, tag: &Tag) -> Option<SamplingDecision> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(operation, tag))
+            .map(|rule| SamplingDecision {
+                sample: rule.sample,
+                retryable: false,
+                tags: vec![
+                    Tag::new(tag::SAMPLER_TYPE_TAG_KEY, tag::SAMPLER_TYPE_DEFERRED),
+                    Tag::new(tag::SAMPLER_PARAM_TAG_KEY, tag.name().to_owned()),
+                ],
+            })
+    }
+}
+
+fn undecided() -> SamplingDecision {
+    SamplingDecision {
+        sample: false,
+        retryable: true,
+        tags: Vec::new(),
+    }
+}
+
+impl Sampler for DeferredSampler {
+    fn is_sampled(&self, trace_id: &TraceId, operation: &str) -> (bool, Vec<Tag>) {
+        self.fallback.is_sampled(trace_id, operation)
+    }
+
+    fn on_create_span(
+        &self,
+        _state: &SpanState,
+        operation: &str,
+        tags: &[Tag],
+    ) -> SamplingDecision {
+        tags.iter()
+            .find_map(|tag| self.decide(operation, tag))
+            .unwrap_or_else(undecided)
+    }
+
+    fn on_set_tag(&self, _state: &SpanState, operation: &str, tag: &Tag) -> SamplingDecision {
+        self.decide(operation, tag).unwrap_or_else(undecided)
+    }
+
+    fn on_finish_span(
+        &self,
+        state: &SpanState,
+        operation: &str,
+        _tags: &[Tag],
+    ) -> SamplingDecision {
+        if !state.is_local_root() {
+            return undecided();
+        }
+
+        let (sample, tags) = self.fallback.is_sampled(state.trace_id(), operation);
+        SamplingDecision {
+            sample,
+            retryable: false,
+            tags,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentracing_rs_core::{SpanBuilder, TagValue};
+
+    use super::*;
+    use crate::testing::SpanRecorder;
+    use crate::{ConstSampler, Span, SpanContext};
+
+    fn recorder(fallback: bool) -> SpanRecorder {
+        SpanRecorder::with_sampler(
+            DeferredSampler::new(ConstSampler::new(fallback))
+                .rule(TagRule::sample(Tag::new("error", true)))
+                .rule(TagRule::reject(Tag::new("health", true)).operation("check")),
+        )
+    }
+
+    fn sampler_type(span: &Span) -> Option<&TagValue> {
+        span.tags()
+            .iter()
+            .find(|tag| tag.name() == tag::SAMPLER_TYPE_TAG_KEY)
+            .map(Tag::value)
+    }
+
+    #[test]
+    fn decides_on_tags_set_at_creation() {
+        let recorder = recorder(false);
+
+        let span = recorder.span("op").tag(Tag::new("error", true)).start();
+        assert!(span.context().state().is_sampled());
+        assert!(span.context().state().is_sampling_final());
+        assert_eq!(
+            sampler_type(&span),
+            Some(&TagValue::String(tag::SAMPLER_TYPE_DEFERRED.to_owned()))
+        );
+
+        let span = recorder.span("check").tag(Tag::new("health", true)).start();
+        assert!(!span.context().state().is_sampled());
+        assert!(span.context().state().is_sampling_final());
+
+        // The operation restriction keeps the rule from matching elsewhere.
+        let span = recorder.span("op").tag(Tag::new("health", true)).start();
+        assert!(!span.context().state().is_sampling_final());
+    }
+
+    #[test]
+    fn decides_on_tags_set_later() {
+        let recorder = recorder(false);
+
+        let root = recorder.span("op").start();
+        let mut child = recorder.span("op").child_of(root.context()).start();
+        assert!(!root.context().state().is_sampling_final());
+
+        child.set_tag(Tag::new("error", true));
+        assert!(root.context().state().is_sampled());
+        assert!(root.context().state().is_sampling_final());
+        assert!(sampler_type(&child).is_some());
+    }
+
+    #[test]
+    fn falls_back_when_local_root_finishes() {
+        let recorder = recorder(true);
+
+        let mut root = recorder.span("op").start();
+        let mut child = recorder.span("op").child_of(root.context()).start();
+
+        child.finish();
+        assert!(!root.context().state().is_sampling_final());
+
+        root.finish();
+        let spans = recorder.finished();
+        assert!(spans[1].context().state().is_sampled());
+        assert!(spans[1].context().state().is_sampling_final());
+    }
+
+    #[test]
+    fn falls_back_for_contexts_deferred_by_the_caller() {
+        let recorder = recorder(true);
+        let remote = SpanContext::new(SpanState::undecided(TraceId::new(), 1), Vec::new());
+
+        let mut root = recorder.span("op").child_of(&remote).start();
+        assert_eq!(root.context().state().parent_span_id(), Some(1));
+        assert!(!root.context().state().is_sampling_final());
+
+        root.finish();
+        let spans = recorder.finished();
+        assert!(spans[0].context().state().is_sampled());
+        assert!(spans[0].context().state().is_sampling_final());
+    }
+}
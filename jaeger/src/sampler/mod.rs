@@ -0,0 +1,147 @@
+use opentracing_rs_core::Tag;
+
+use crate::{tag, Error, Result, SpanState, TraceId};
+
+mod adaptive;
+mod deferred;
+mod per_operation;
+mod rate_limiting;
+#[cfg(feature = "tokio")]
+mod remote;
+mod rule;
+
+pub(crate) use self::rate_limiting::RateLimiter;
+#[cfg(feature = "tokio")]
+pub use self::remote::{RemoteSampler, RemoteSamplerBuilder, RemoteSamplerError};
+pub use self::{
+    adaptive::{AdaptiveSampler, AdaptiveSamplerBuilder, OperationRate},
+    deferred::{DeferredSampler, TagRule},
+    per_operation::{GuaranteedThroughputSampler, PerOperationSampler},
+    rate_limiting::RateLimitingSampler,
+    rule::{RuleSampler, RuleSamplerError, SamplingRule},
+};
+
+/// Outcome of a sampling decision. A retryable decision lets the sampler
+/// decide again later in the life of the trace, otherwise it is final.
+#[derive(Clone, Debug)]
+pub struct SamplingDecision {
+    pub sample: bool,
+    pub retryable: bool,
+    pub tags: Vec<Tag>,
+}
+
+/// Decides which traces get sampled.
+///
+/// `is_sampled` decides once, when a trace starts. Samplers that need to see
+/// more of a trace implement the `on_*` hooks instead, which are called for
+/// every span of the trace until a decision is final.
+pub trait Sampler: Send + Sync {
+    fn is_sampled(&self, trace_id: &TraceId, operation: &str) -> (bool, Vec<Tag>);
+
+    /// Called when a span starts, with the tags it was built with.
+    fn on_create_span(
+        &self,
+        state: &SpanState,
+        operation: &str,
+        _tags: &[Tag],
+    ) -> SamplingDecision {
+        let (sample, tags) = self.is_sampled(state.trace_id(), operation);
+        SamplingDecision {
+            sample,
+            retryable: false,
+            tags,
+        }
+    }
+
+    /// Called when a tag is set on a started span.
+    fn on_set_tag(&self, _state: &SpanState, _operation: &str, _tag: &Tag) -> SamplingDecision {
+        SamplingDecision {
+            sample: false,
+            retryable: true,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Called when a span finishes, with all of its tags.
+    fn on_finish_span(
+        &self,
+        _state: &SpanState,
+        _operation: &str,
+        _tags: &[Tag],
+    ) -> SamplingDecision {
+        SamplingDecision {
+            sample: false,
+            retryable: true,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Stops the background work of the sampler, called when the tracer
+    /// closes.
+    fn close(&self) {}
+}
+
+pub struct ConstSampler {
+    decision: bool,
+    tags: Vec<Tag>,
+}
+
+impl ConstSampler {
+    pub fn new(sample: bool) -> Self {
+        let mut tags = Vec::with_capacity(2);
+        tags.push(Tag::new(tag::SAMPLER_TYPE_TAG_KEY, tag::SAMPLER_TYPE_CONST));
+
+        Self {
+            decision: sample,
+            tags,
+        }
+    }
+}
+
+impl Sampler for ConstSampler {
+    fn is_sampled(&self, _trace_id: &TraceId, _operation: &str) -> (bool, Vec<Tag>) {
+        (self.decision, self.tags.clone())
+    }
+}
+
+pub struct ProbabilisticSampler {
+    sampling_rate: f64,
+    sampling_boundary: u64,
+    tags: Vec<Tag>,
+}
+
+impl ProbabilisticSampler {
+    pub fn new(sampling_rate: f64) -> Result<Self> {
+        if !(0.0..=1.0).contains(&sampling_rate) {
+            return Err(Error::InvalidSamplingRate(sampling_rate));
+        }
+
+        Ok(Self::clamped(sampling_rate))
+    }
+
+    /// Samples at `sampling_rate`, clamped to `[0.0, 1.0]`.
+    pub(crate) fn clamped(sampling_rate: f64) -> Self {
+        let sampling_rate = sampling_rate.clamp(0.0, 1.0);
+        let tags = vec![
+            Tag::new(tag::SAMPLER_TYPE_TAG_KEY, tag::SAMPLER_TYPE_PROBABILISTIC),
+            Tag::new(tag::SAMPLER_PARAM_TAG_KEY, sampling_rate),
+        ];
+
+        let sampling_boundary = (u64::MAX as f64 * sampling_rate) as u64;
+        Self {
+            sampling_boundary,
+            sampling_rate,
+            tags,
+        }
+    }
+
+    pub fn sampling_rate(&self) -> f64 {
+        self.sampling_rate
+    }
+}
+
+impl Sampler for ProbabilisticSampler {
+    fn is_sampled(&self, trace_id: &TraceId, _operation: &str) -> (bool, Vec<Tag>) {
+        (self.sampling_boundary > trace_id.low, self.tags.clone())
+    }
+}
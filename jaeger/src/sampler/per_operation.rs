@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, RwLock};
+
+use opentracing_rs_core::Tag;
+
+use super::{rate_limiting::check_rate_limit, ProbabilisticSampler, RateLimiter, Sampler};
+use crate::{tag, Result, TraceId};
+
+/// Samples probabilistically, while guaranteeing at least
+/// `lower_bound_traces_per_second` traces.
+pub struct GuaranteedThroughputSampler {
+    probabilistic_sampler: ProbabilisticSampler,
+    lower_bound_limiter: Mutex<RateLimiter>,
+    lower_bound_tags: Vec<Tag>,
+}
+
+impl GuaranteedThroughputSampler {
+    pub fn new(sampling_rate: f64, lower_bound_traces_per_second: f64) -> Result<Self> {
+        check_rate_limit(lower_bound_traces_per_second)?;
+
+        Ok(Self::with_valid_rates(
+            ProbabilisticSampler::new(sampling_rate)?,
+            lower_bound_traces_per_second,
+        ))
+    }
+
+    fn with_valid_rates(
+        probabilistic_sampler: ProbabilisticSampler,
+        lower_bound_traces_per_second: f64,
+    ) -> Self {
+        let sampling_rate = probabilistic_sampler.sampling_rate();
+        let lower_bound_tags = vec![
+            Tag::new(tag::SAMPLER_TYPE_TAG_KEY, tag::SAMPLER_TYPE_LOWER_BOUND),
+            Tag::new(tag::SAMPLER_PARAM_TAG_KEY, sampling_rate),
+        ];
+
+        Self {
+            probabilistic_sampler,
+            lower_bound_limiter: Mutex::new(RateLimiter::new(
+                lower_bound_traces_per_second,
+                lower_bound_traces_per_second.max(1.0),
+            )),
+            lower_bound_tags,
+        }
+    }
+}
+
+impl Sampler for GuaranteedThroughputSampler {
+    fn is_sampled(&self, trace_id: &TraceId, operation: &str) -> (bool, Vec<Tag>) {
+        let (sampled, tags) = self.probabilistic_sampler.is_sampled(trace_id, operation);
+        // Traces sampled probabilistically still count against the lower bound.
+        let lower_bound_sampled = self.lower_bound_limiter.lock().unwrap().check_credit(1.0);
+
+        if sampled {
+            (true, tags)
+        } else {
+            (lower_bound_sampled, self.lower_bound_tags.clone())
+        }
+    }
+}
+
+/// Samples every operation with its own `GuaranteedThroughputSampler`.
+///
+/// Operations without a configured sampling rate get the default one, until
+/// `max_operations` operations are tracked. Past that, new operations are
+/// sampled by a plain `ProbabilisticSampler` at the default rate.
+pub struct PerOperationSampler {
+    default_sampler: ProbabilisticSampler,
+    lower_bound_traces_per_second: f64,
+    max_operations: usize,
+    samplers: RwLock<HashMap<String, GuaranteedThroughputSampler>>,
+}
+
+impl PerOperationSampler {
+    pub fn new(
+        default_sampling_rate: f64,
+        lower_bound_traces_per_second: f64,
+        max_operations: usize,
+    ) -> Result<Self> {
+        check_rate_limit(lower_bound_traces_per_second)?;
+
+        Ok(Self {
+            default_sampler: ProbabilisticSampler::new(default_sampling_rate)?,
+            lower_bound_traces_per_second,
+            max_operations,
+            samplers: RwLock::new(HashMap::new()),
+        })
+    }
+
+    /// Samples `operation` at `sampling_rate` instead of the default rate.
+    pub fn with_operation<N>(self, operation: N, sampling_rate: f64) -> Result<Self>
+    where
+        N: Into<String>,
+    {
+        let sampler =
+            GuaranteedThroughputSampler::new(sampling_rate, self.lower_bound_traces_per_second)?;
+        self.samplers
+            .write()
+            .unwrap()
+            .insert(operation.into(), sampler);
+        Ok(self)
+    }
+}
+
+impl Sampler for PerOperationSampler {
+    fn is_sampled(&self, trace_id: &TraceId, operation: &str) -> (bool, Vec<Tag>) {
+        if let Some(sampler) = self.samplers.read().unwrap().get(operation) {
+            return sampler.is_sampled(trace_id, operation);
+        }
+
+        let mut samplers = self.samplers.write().unwrap();
+        if !samplers.contains_key(operation) && samplers.len() >= self.max_operations {
+            return self.default_sampler.is_sampled(trace_id, operation);
+        }
+
+        samplers
+            .entry(operation.to_owned())
+            .or_insert_with(|| {
+                GuaranteedThroughputSampler::with_valid_rates(
+                    ProbabilisticSampler::clamped(self.default_sampler.sampling_rate()),
+                    self.lower_bound_traces_per_second,
+                )
+            })
+            .is_sampled(trace_id, operation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentracing_rs_core::TagValue;
+
+    use super::*;
+
+    fn sampler_type(tags: &[Tag]) -> &str {
+        let value = tags
+            .iter()
+            .find(|tag| tag.name() == tag::SAMPLER_TYPE_TAG_KEY)
+            .map(Tag::value);
+        match value {
+            Some(TagValue::String(sampler_type)) => sampler_type,
+            value => panic!("unexpected sampler type {:?}", value),
+        }
+    }
+
+    #[test]
+    fn lower_bound_samples_rejected_traces() {
+        let sampler = GuaranteedThroughputSampler::new(0.0, 1.0).unwrap();
+        let trace_id = TraceId::new();
+
+        let (sampled, tags) = sampler.is_sampled(&trace_id, "op");
+        assert!(sampled);
+        assert_eq!(sampler_type(&tags), tag::SAMPLER_TYPE_LOWER_BOUND);
+
+        let (sampled, tags) = sampler.is_sampled(&trace_id, "op");
+        assert!(!sampled);
+        assert_eq!(sampler_type(&tags), tag::SAMPLER_TYPE_LOWER_BOUND);
+    }
+
+    #[test]
+    fn probabilistic_decisions_keep_their_tags() {
+        let sampler = GuaranteedThroughputSampler::new(1.0, 1.0).unwrap();
+
+        let (sampled, tags) = sampler.is_sampled(&TraceId::new(), "op");
+        assert!(sampled);
+        assert_eq!(sampler_type(&tags), tag::SAMPLER_TYPE_PROBABILISTIC);
+    }
+
+    #[test]
+    fn caps_tracked_operations() {
+        let sampler = PerOperationSampler::new(0.0, 1.0, 2)
+            .unwrap()
+            .with_operation("checkout", 1.0)
+            .unwrap();
+        let trace_id = TraceId::new();
+
+        let (sampled, tags) = sampler.is_sampled(&trace_id, "checkout");
+        assert!(sampled);
+        assert_eq!(sampler_type(&tags), tag::SAMPLER_TYPE_PROBABILISTIC);
+
+        // The second operation is tracked, and guaranteed a trace.
+        let (sampled, tags) = sampler.is_sampled(&trace_id, "search");
+        assert!(sampled);
+        assert_eq!(sampler_type(&tags), tag::SAMPLER_TYPE_LOWER_BOUND);
+
+        // Past the cap, operations get the default sampler only.
+        let (sampled, tags) = sampler.is_sampled(&trace_id, "cart");
+        assert!(!sampled);
+        assert_eq!(sampler_type(&tags), tag::SAMPLER_TYPE_PROBABILISTIC);
+        assert_eq!(sampler.samplers.read().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,154 @@
+use std::sync::Mutex;
+use std::time::Instant;
+
+use opentracing_rs_core::Tag;
+
+use super::Sampler;
+use crate::{tag, Error, Result, TraceId};
+
+/// Rejects negative and NaN rate limits.
+pub(crate) fn check_rate_limit(credits_per_second: f64) -> Result<()> {
+    if credits_per_second < 0.0 || credits_per_second.is_nan() {
+        return Err(Error::InvalidRateLimit(credits_per_second));
+    }
+    Ok(())
+}
+
+/// Samples at most `max_traces_per_second` traces, allowing bursts of up to
+/// one second worth of traces.
+pub struct RateLimitingSampler {
+    max_traces_per_second: f64,
+    rate_limiter: Mutex<RateLimiter>,
+    tags: Vec<Tag>,
+}
+
+impl RateLimitingSampler {
+    pub fn new(max_traces_per_second: f64) -> Result<Self> {
+        check_rate_limit(max_traces_per_second)?;
+
+        let tags = vec![
+            Tag::new(tag::SAMPLER_TYPE_TAG_KEY, tag::SAMPLER_TYPE_RATE_LIMITING),
+            Tag::new(tag::SAMPLER_PARAM_TAG_KEY, max_traces_per_second),
+        ];
+
+        let max_balance = max_traces_per_second.max(1.0);
+        Ok(Self {
+            max_traces_per_second,
+            rate_limiter: Mutex::new(RateLimiter::new(max_traces_per_second, max_balance)),
+            tags,
+        })
+    }
+
+    pub fn max_traces_per_second(&self) -> f64 {
+        self.max_traces_per_second
+    }
+}
+
+impl Sampler for RateLimitingSampler {
+    fn is_sampled(&self, _trace_id: &TraceId, _operation: &str) -> (bool, Vec<Tag>) {
+        let sampled = self.rate_limiter.lock().unwrap().check_credit(1.0);
+        (sampled, self.tags.clone())
+    }
+}
+
+/// Token bucket accruing `credits_per_second` credits, up to `max_balance`.
+pub(crate) struct RateLimiter {
+    credits_per_second: f64,
+    max_balance: f64,
+    balance: f64,
+    last_tick: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(credits_per_second: f64, max_balance: f64) -> Self {
+        Self {
+            credits_per_second,
+            max_balance,
+            balance: max_balance,
+            last_tick: Instant::now(),
+        }
+    }
+
+    /// Spends `cost` credits if the balance allows it.
+    pub(crate) fn check_credit(&mut self, cost: f64) -> bool {
+        self.check_credit_at(cost, Instant::now())
+    }
+
+    fn check_credit_at(&mut self, cost: f64, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.last_tick);
+        self.last_tick = now;
+
+        self.balance =
+            (self.balance + elapsed.as_secs_f64() * self.credits_per_second).min(self.max_balance);
+
+        if self.balance >= cost {
+            self.balance -= cost;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn allows_bursts_up_to_max_balance() {
+        let mut limiter = RateLimiter::new(1.0, 3.0);
+        let now = limiter.last_tick;
+
+        for _ in 0..3 {
+            assert!(limiter.check_credit_at(1.0, now));
+        }
+        assert!(!limiter.check_credit_at(1.0, now));
+    }
+
+    #[test]
+    fn refills_over_elapsed_time() {
+        let mut limiter = RateLimiter::new(2.0, 2.0);
+        let start = limiter.last_tick;
+        assert!(limiter.check_credit_at(2.0, start));
+        assert!(!limiter.check_credit_at(1.0, start));
+
+        // Half a second earns one credit.
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.check_credit_at(1.0, later));
+        assert!(!limiter.check_credit_at(1.0, later));
+
+        // Long idle periods do not earn more than the maximum balance.
+        let much_later = later + Duration::from_secs(60);
+        assert!(limiter.check_credit_at(2.0, much_later));
+        assert!(!limiter.check_credit_at(1.0, much_later));
+    }
+
+    #[test]
+    fn rejects_costs_above_the_balance() {
+        let mut limiter = RateLimiter::new(1.0, 1.0);
+        let now = limiter.last_tick;
+
+        assert!(!limiter.check_credit_at(1.5, now));
+        assert!(limiter.check_credit_at(0.5, now));
+        assert!(limiter.check_credit_at(0.5, now));
+        assert!(!limiter.check_credit_at(0.5, now));
+    }
+
+    #[test]
+    fn samples_one_second_worth_of_traces() {
+        let trace_id = TraceId::new();
+
+        let sampler = RateLimitingSampler::new(3.0).unwrap();
+        let sampled = (0..5)
+            .filter(|_| sampler.is_sampled(&trace_id, "op").0)
+            .count();
+        assert_eq!(sampled, 3);
+
+        // Rates below one trace per second still allow a single trace.
+        let sampler = RateLimitingSampler::new(0.1).unwrap();
+        assert!(sampler.is_sampled(&trace_id, "op").0);
+        assert!(!sampler.is_sampled(&trace_id, "op").0);
+    }
+}
@@ -0,0 +1,626 @@
+use std::error;
+use std::fmt;
+use std::result;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use futures::Future;
+use hyper::{client::HttpConnector, Client, StatusCode, Uri};
+use serde::Deserialize;
+
+use opentracing_rs_core::Tag;
+
+use super::{PerOperationSampler, ProbabilisticSampler, RateLimitingSampler, Sampler};
+use crate::{
+    runtime, signal::CloseSignal, Error, ErrorHandler, LoggingErrorHandler, Result, TraceId,
+};
+
+const DEFAULT_SAMPLING_SERVER_URL: &str = "http://localhost:5778/sampling";
+const DEFAULT_SAMPLING_RATE: f64 = 0.001;
+const DEFAULT_MAX_OPERATIONS: usize = 2000;
+
+#[derive(Debug)]
+pub enum RemoteSamplerError {
+    Request(hyper::Error),
+    Timeout,
+    /// The agent answered with a non-success status code.
+    Status(StatusCode),
+    Parse(serde_json::Error),
+    /// The strategy parses but its rates are out of range.
+    InvalidStrategy,
+}
+
+impl fmt::Display for RemoteSamplerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RemoteSamplerError::Request(err) => {
+                write!(f, "failed to fetch sampling strategy: {}", err)
+            }
+            RemoteSamplerError::Timeout => write!(f, "timed out fetching sampling strategy"),
+            RemoteSamplerError::Status(status) => {
+                write!(f, "sampling server answered with status {}", status)
+            }
+            RemoteSamplerError::Parse(err) => {
+                write!(f, "failed to parse sampling strategy: {}", err)
+            }
+            RemoteSamplerError::InvalidStrategy => {
+                write!(f, "sampling strategy has out of range rates")
+            }
+        }
+    }
+}
+
+impl error::Error for RemoteSamplerError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RemoteSamplerError::Request(err) => Some(err),
+            RemoteSamplerError::Parse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Samples with the strategy served by the Jaeger agent for a service.
+///
+/// The strategy is fetched from the sampling server URL with a `service`
+/// query parameter added, every refresh interval. Until the first one
+/// arrives, and whenever fetching fails, the last known sampler keeps
+/// deciding and the failure goes to the error handler.
+///
+/// Requires the `tokio` feature.
+#[derive(Clone)]
+pub struct RemoteSampler {
+    inner: Arc<RwLock<Inner>>,
+    closed: CloseSignal,
+}
+
+struct Inner {
+    strategy: Option<SamplingStrategyResponse>,
+    sampler: Box<dyn Sampler>,
+    max_operations: usize,
+}
+
+impl RemoteSampler {
+    pub fn builder<N>(service_name: N) -> RemoteSamplerBuilder
+    where
+        N: Into<String>,
+    {
+        RemoteSamplerBuilder::new(service_name)
+    }
+
+    /// Replaces the current sampler if `strategy` differs from the one it was
+    /// built from, keeping it if `strategy` is invalid.
+    fn update(&self, strategy: SamplingStrategyResponse) -> result::Result<(), RemoteSamplerError> {
+        let mut inner = self.inner.write().unwrap();
+        if inner.strategy.as_ref() == Some(&strategy) {
+            return Ok(());
+        }
+
+        let sampler = strategy
+            .to_sampler(inner.max_operations)
+            .ok_or(RemoteSamplerError::InvalidStrategy)?;
+        inner.sampler = sampler;
+        inner.strategy = Some(strategy);
+        Ok(())
+    }
+}
+
+impl Sampler for RemoteSampler {
+    fn is_sampled(&self, trace_id: &TraceId, operation: &str) -> (bool, Vec<Tag>) {
+        self.inner
+            .read()
+            .unwrap()
+            .sampler
+            .is_sampled(trace_id, operation)
+    }
+
+    /// Stops polling for strategies, the last known sampler keeps deciding.
+    fn close(&self) {
+        self.closed.close();
+    }
+}
+
+/// Body of the agent's `/sampling` response.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct SamplingStrategyResponse {
+    probabilistic_sampling: Option<ProbabilisticSamplingStrategy>,
+    rate_limiting_sampling: Option<RateLimitingSamplingStrategy>,
+    operation_sampling: Option<PerOperationSamplingStrategies>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct ProbabilisticSamplingStrategy {
+    sampling_rate: f64,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct RateLimitingSamplingStrategy {
+    max_traces_per_second: f64,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct PerOperationSamplingStrategies {
+    default_sampling_probability: f64,
+    default_lower_bound_traces_per_second: f64,
+    #[serde(default)]
+    per_operation_strategies: Vec<OperationSamplingStrategy>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+struct OperationSamplingStrategy {
+    operation: String,
+    probabilistic_sampling: ProbabilisticSamplingStrategy,
+}
+
+impl SamplingStrategyResponse {
+    /// Builds the sampler for this strategy, or `None` if it is invalid.
+    /// Per-operation strategies take precedence over the others.
+    fn to_sampler(&self, max_operations: usize) -> Option<Box<dyn Sampler>> {
+        if let Some(strategies) = &self.operation_sampling {
+            let sampler = strategies.per_operation_strategies.iter().try_fold(
+                PerOperationSampler::new(
+                    strategies.default_sampling_probability,
+                    strategies.default_lower_bound_traces_per_second,
+                    max_operations,
+                )
+                .ok()?,
+                |sampler, strategy| {
+                    sampler
+                        .with_operation(
+                            strategy.operation.as_str(),
+                            strategy.probabilistic_sampling.sampling_rate,
+                        )
+                        .ok()
+                },
+            )?;
+            return Some(Box::new(sampler));
+        }
+
+        if let Some(strategy) = &self.rate_limiting_sampling {
+            let sampler = RateLimitingSampler::new(strategy.max_traces_per_second).ok()?;
+            return Some(Box::new(sampler));
+        }
+
+        let strategy = self.probabilistic_sampling.as_ref()?;
+        let sampler = ProbabilisticSampler::new(strategy.sampling_rate).ok()?;
+        Some(Box::new(sampler))
+    }
+}
+
+pub struct RemoteSamplerBuilder {
+    service_name: String,
+    sampling_server_url: Option<Uri>,
+    initial_sampler: Option<Box<dyn Sampler>>,
+    refresh_interval: Duration,
+    max_operations: usize,
+    error_handler: Option<Arc<dyn ErrorHandler>>,
+}
+
+impl RemoteSamplerBuilder {
+    fn new<N>(service_name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        Self {
+            service_name: service_name.into(),
+            sampling_server_url: None,
+            initial_sampler: None,
+            refresh_interval: Duration::from_secs(60),
+            max_operations: DEFAULT_MAX_OPERATIONS,
+            error_handler: None,
+        }
+    }
+
+    /// Sets the strategies endpoint, `http://localhost:5778/sampling` by
+    /// default.
+    pub fn sampling_server_url(mut self, url: Uri) -> Self {
+        self.sampling_server_url = Some(url);
+        self
+    }
+
+    /// Sets the sampler used until a strategy has been fetched, a
+    /// `ProbabilisticSampler` at 0.001 by default.
+    pub fn initial_sampler<S>(mut self, sampler: S) -> Self
+    where
+        S: Sampler + 'static,
+    {
+        self.initial_sampler = Some(Box::new(sampler));
+        self
+    }
+
+    pub fn refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.refresh_interval = refresh_interval;
+        self
+    }
+
+    /// Caps the operations tracked by a per-operation strategy, 2000 by
+    /// default.
+    pub fn max_operations(mut self, max_operations: usize) -> Self {
+        self.max_operations = max_operations;
+        self
+    }
+
+    /// Sets the handler of strategies failing to fetch or parse, which are
+    /// printed to stderr by default.
+    pub fn error_handler<H>(mut self, error_handler: H) -> Self
+    where
+        H: ErrorHandler + 'static,
+    {
+        self.error_handler = Some(Arc::new(error_handler));
+        self
+    }
+
+    pub(crate) fn shared_error_handler(mut self, error_handler: Arc<dyn ErrorHandler>) -> Self {
+        self.error_handler = Some(error_handler);
+        self
+    }
+
+    /// Builds the sampler along with the future polling for strategies.
+    pub fn build_and_serve(self) -> Result<(RemoteSampler, impl Future<Output = ()>)> {
+        let sampler = RemoteSampler {
+            inner: Arc::new(RwLock::new(Inner {
+                strategy: None,
+                sampler: self.initial_sampler.unwrap_or_else(|| {
+                    Box::new(ProbabilisticSampler::clamped(DEFAULT_SAMPLING_RATE))
+                }),
+                max_operations: self.max_operations,
+            })),
+            closed: CloseSignal::default(),
+        };
+
+        let sampling_server_url = self
+            .sampling_server_url
+            .unwrap_or_else(|| Uri::from_static(DEFAULT_SAMPLING_SERVER_URL));
+        let url = strategy_url(sampling_server_url, &self.service_name)?;
+
+        let client = Client::new();
+        let refresh_interval = self.refresh_interval;
+        let error_handler = self
+            .error_handler
+            .unwrap_or_else(|| Arc::new(LoggingErrorHandler::default()));
+        let updated = sampler.clone();
+
+        let serve = sampler.closed.guard(async move {
+            loop {
+                let fetched =
+                    runtime::timeout(refresh_interval, fetch_strategy(&client, url.clone())).await;
+                let updated = fetched
+                    .unwrap_or(Err(RemoteSamplerError::Timeout))
+                    .and_then(|strategy| updated.update(strategy));
+                if let Err(err) = updated {
+                    error_handler.handle(err.into());
+                }
+                runtime::sleep(refresh_interval).await;
+            }
+        });
+
+        Ok((sampler, serve))
+    }
+}
+
+/// Adds the `service` parameter to the query of `sampling_server_url`.
+fn strategy_url(sampling_server_url: Uri, service_name: &str) -> Result<Uri> {
+    let invalid_url = |err: &dyn fmt::Display| {
+        Error::InvalidConfig(format!("invalid sampling server url: {}", err))
+    };
+
+    let mut query = form_urlencoded::Serializer::new(
+        sampling_server_url.query().unwrap_or_default().to_owned(),
+    );
+    query.append_pair("service", service_name);
+    let path_and_query = format!("{}?{}", sampling_server_url.path(), query.finish())
+        .parse()
+        .map_err(|err| invalid_url(&err))?;
+
+    let mut parts = sampling_server_url.into_parts();
+    parts.path_and_query = Some(path_and_query);
+    Uri::from_parts(parts).map_err(|err| invalid_url(&err))
+}
+
+/// Fetches the current strategy.
+async fn fetch_strategy(
+    client: &Client<HttpConnector>,
+    url: Uri,
+) -> result::Result<SamplingStrategyResponse, RemoteSamplerError> {
+    let response = client.get(url).await.map_err(RemoteSamplerError::Request)?;
+    if !response.status().is_success() {
+        return Err(RemoteSamplerError::Status(response.status()));
+    }
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(RemoteSamplerError::Request)?;
+
+    serde_json::from_slice(&body).map_err(RemoteSamplerError::Parse)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::Instant;
+
+    use opentracing_rs_core::TagValue;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{tag, testing, ConstSampler};
+
+    const PROBABILISTIC: &str = r#"{"probabilisticSampling": {"samplingRate": 1.0}}"#;
+    const RATE_LIMITING: &str = r#"{"rateLimitingSampling": {"maxTracesPerSecond": 7}}"#;
+    const PER_OPERATION: &str = r#"{
+        "probabilisticSampling": {"samplingRate": 0.5},
+        "operationSampling": {
+            "defaultSamplingProbability": 0.0,
+            "defaultLowerBoundTracesPerSecond": 0.0,
+            "perOperationStrategies": [
+                {"operation": "checkout", "probabilisticSampling": {"samplingRate": 1.0}}
+            ]
+        }
+    }"#;
+
+    /// Stand-in for the agent's sampling endpoint, answering every request
+    /// with the current response and recording the request targets.
+    #[derive(Clone)]
+    struct SamplingServer {
+        url: Uri,
+        response: Arc<Mutex<(u16, String)>>,
+        targets: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl SamplingServer {
+        async fn start(path_and_query: &str) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let server = SamplingServer {
+                url: format!(
+                    "http://{}{}",
+                    listener.local_addr().unwrap(),
+                    path_and_query
+                )
+                .parse()
+                .unwrap(),
+                response: Arc::new(Mutex::new((500, String::new()))),
+                targets: Arc::new(Mutex::new(Vec::new())),
+            };
+
+            let serving = server.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    let mut head = Vec::new();
+                    while !head.ends_with(b"\r\n\r\n") {
+                        let mut byte = [0];
+                        if stream.read(&mut byte).await.unwrap() == 0 {
+                            break;
+                        }
+                        head.push(byte[0]);
+                    }
+                    let head = String::from_utf8(head).unwrap();
+                    let target = head.split(' ').nth(1).unwrap_or_default().to_owned();
+                    serving.targets.lock().unwrap().push(target);
+
+                    let (status, body) = serving.response.lock().unwrap().clone();
+                    let response = format!(
+                        "HTTP/1.1 {} X\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                }
+            });
+
+            server
+        }
+
+        fn respond(&self, status: u16, body: &str) {
+            *self.response.lock().unwrap() = (status, body.to_owned());
+        }
+    }
+
+    fn build_sampler(
+        server: &SamplingServer,
+    ) -> (RemoteSampler, Arc<Mutex<Vec<Error>>>, CloseOnDrop) {
+        let (error_handler, errors) = testing::collecting_error_handler();
+        let (sampler, serve) = RemoteSampler::builder("checkout service")
+            .sampling_server_url(server.url.clone())
+            .initial_sampler(ConstSampler::new(false))
+            .refresh_interval(Duration::from_millis(20))
+            .error_handler(error_handler)
+            .build_and_serve()
+            .unwrap();
+        tokio::spawn(serve);
+
+        (sampler.clone(), errors, CloseOnDrop(sampler))
+    }
+
+    struct CloseOnDrop(RemoteSampler);
+
+    impl Drop for CloseOnDrop {
+        fn drop(&mut self) {
+            self.0.close();
+        }
+    }
+
+    /// The `sampler.type` and `sampler.param` tags of the decision on
+    /// `operation`.
+    fn sampler_tags(sampler: &RemoteSampler, operation: &str) -> (String, Option<f64>) {
+        let (_, tags) = sampler.is_sampled(&TraceId::new(), operation);
+        let mut sampler_type = String::new();
+        let mut param = None;
+        for tag in tags {
+            match (tag.name(), tag.value()) {
+                (tag::SAMPLER_TYPE_TAG_KEY, TagValue::String(value)) => {
+                    sampler_type = value.clone()
+                }
+                (tag::SAMPLER_PARAM_TAG_KEY, TagValue::Double(value)) => param = Some(*value),
+                _ => {}
+            }
+        }
+        (sampler_type, param)
+    }
+
+    type ErrorMatcher = fn(&RemoteSamplerError) -> bool;
+
+    async fn wait_for_error<F>(errors: &Mutex<Vec<Error>>, expected: F)
+    where
+        F: Fn(&RemoteSamplerError) -> bool,
+    {
+        wait_until(|| {
+            errors.lock().unwrap().iter().any(|err| match err {
+                Error::RemoteSampler(err) => expected(err),
+                _ => false,
+            })
+        })
+        .await;
+    }
+
+    async fn wait_until<F>(condition: F)
+    where
+        F: Fn() -> bool,
+    {
+        let started = Instant::now();
+        while !condition() {
+            assert!(
+                started.elapsed() < Duration::from_secs(5),
+                "condition not met in time"
+            );
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn follows_served_strategies() {
+        let server = SamplingServer::start("/sampling").await;
+        let (sampler, errors, _close) = build_sampler(&server);
+        assert_eq!(
+            sampler_tags(&sampler, "checkout").0,
+            tag::SAMPLER_TYPE_CONST
+        );
+
+        server.respond(200, PROBABILISTIC);
+        let probabilistic = (tag::SAMPLER_TYPE_PROBABILISTIC.to_owned(), Some(1.0));
+        wait_until(|| sampler_tags(&sampler, "checkout") == probabilistic).await;
+        assert!(sampler.is_sampled(&TraceId::new(), "checkout").0);
+
+        server.respond(200, RATE_LIMITING);
+        let rate_limiting = (tag::SAMPLER_TYPE_RATE_LIMITING.to_owned(), Some(7.0));
+        wait_until(|| sampler_tags(&sampler, "checkout") == rate_limiting).await;
+
+        server.respond(200, PER_OPERATION);
+        wait_until(|| sampler_tags(&sampler, "checkout") == probabilistic).await;
+        assert_eq!(
+            sampler_tags(&sampler, "search"),
+            (tag::SAMPLER_TYPE_LOWER_BOUND.to_owned(), Some(0.0))
+        );
+
+        // Only a slow test machine may have timed out a request.
+        assert!(errors
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|err| matches!(err, Error::RemoteSampler(RemoteSamplerError::Timeout))));
+        let targets = server.targets.lock().unwrap();
+        assert!(targets
+            .iter()
+            .all(|target| target == "/sampling?service=checkout+service"));
+    }
+
+    #[tokio::test]
+    async fn keeps_last_strategy_on_failure() {
+        let server = SamplingServer::start("/sampling").await;
+        let (sampler, errors, _close) = build_sampler(&server);
+
+        // Nothing fetched yet, the initial sampler keeps deciding.
+        wait_for_error(&errors, |err| {
+            matches!(
+                err,
+                RemoteSamplerError::Status(StatusCode::INTERNAL_SERVER_ERROR)
+            )
+        })
+        .await;
+        assert_eq!(
+            sampler_tags(&sampler, "checkout").0,
+            tag::SAMPLER_TYPE_CONST
+        );
+
+        server.respond(200, PROBABILISTIC);
+        let probabilistic = (tag::SAMPLER_TYPE_PROBABILISTIC.to_owned(), Some(1.0));
+        wait_until(|| sampler_tags(&sampler, "checkout") == probabilistic).await;
+
+        let failures: Vec<(u16, &str, ErrorMatcher)> = vec![
+            (503, "", |err| {
+                matches!(
+                    err,
+                    RemoteSamplerError::Status(StatusCode::SERVICE_UNAVAILABLE)
+                )
+            }),
+            (200, "not json", |err| {
+                matches!(err, RemoteSamplerError::Parse(_))
+            }),
+            (
+                200,
+                r#"{"probabilisticSampling": {"samplingRate": 2.0}}"#,
+                |err| matches!(err, RemoteSamplerError::InvalidStrategy),
+            ),
+        ];
+        for (status, body, expected) in failures {
+            errors.lock().unwrap().clear();
+            server.respond(status, body);
+            wait_for_error(&errors, expected).await;
+            assert_eq!(sampler_tags(&sampler, "checkout"), probabilistic);
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_unreachable_server() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/sampling", listener.local_addr().unwrap());
+        drop(listener);
+
+        let (error_handler, errors) = testing::collecting_error_handler();
+        let (sampler, serve) = RemoteSampler::builder("checkout")
+            .sampling_server_url(url.parse().unwrap())
+            .refresh_interval(Duration::from_millis(20))
+            .error_handler(error_handler)
+            .build_and_serve()
+            .unwrap();
+        let _close = CloseOnDrop(sampler.clone());
+        tokio::spawn(serve);
+
+        wait_for_error(&errors, |err| matches!(err, RemoteSamplerError::Request(_))).await;
+        assert_eq!(
+            sampler_tags(&sampler, "checkout"),
+            (
+                tag::SAMPLER_TYPE_PROBABILISTIC.to_owned(),
+                Some(DEFAULT_SAMPLING_RATE)
+            )
+        );
+    }
+
+    #[test]
+    fn adds_service_to_the_query() {
+        let url = |base: &'static str, service_name| {
+            strategy_url(Uri::from_static(base), service_name)
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(
+            url(DEFAULT_SAMPLING_SERVER_URL, "checkout"),
+            "http://localhost:5778/sampling?service=checkout"
+        );
+        assert_eq!(
+            url("http://agent:5778/sampling?env=prod", "checkout"),
+            "http://agent:5778/sampling?env=prod&service=checkout"
+        );
+        assert_eq!(
+            url("http://agent:5778", "a&b=c d"),
+            "http://agent:5778/?service=a%26b%3Dc+d"
+        );
+    }
+}
@@ -0,0 +1,585 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use opentracing_rs_core::{Tag, TagValue};
+
+use super::{ConstSampler, ProbabilisticSampler, RateLimitingSampler, Sampler, SamplingDecision};
+use crate::{SpanState, TraceId};
+
+#[derive(Debug)]
+pub enum RuleSamplerError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    /// The document parsed, but describes an invalid sampler.
+    Invalid(String),
+}
+
+impl fmt::Display for RuleSamplerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleSamplerError::Io(err) => write!(f, "failed to read sampling rules: {}", err),
+            RuleSamplerError::Toml(err) => write!(f, "invalid TOML sampling rules: {}", err),
+            RuleSamplerError::Json(err) => write!(f, "invalid JSON sampling rules: {}", err),
+            RuleSamplerError::Invalid(reason) => write!(f, "invalid sampling rules: {}", reason),
+        }
+    }
+}
+
+impl Error for RuleSamplerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RuleSamplerError::Io(err) => Some(err),
+            RuleSamplerError::Toml(err) => Some(err),
+            RuleSamplerError::Json(err) => Some(err),
+            RuleSamplerError::Invalid(_) => None,
+        }
+    }
+}
+
+/// Selects the sampler of the spans it matches.
+///
+/// A rule without conditions matches every span.
+pub struct SamplingRule {
+    operation: Option<String>,
+    tags: Vec<Tag>,
+    sampler: Box<dyn Sampler>,
+}
+
+impl SamplingRule {
+    pub fn new<S>(sampler: S) -> Self
+    where
+        S: Sampler + 'static,
+    {
+        Self {
+            operation: None,
+            tags: Vec::new(),
+            sampler: Box::new(sampler),
+        }
+    }
+
+    /// Only matches operations matching `pattern`, where `*` matches any
+    /// sequence of characters and `?` any single character.
+    pub fn operation<P>(mut self, pattern: P) -> Self
+    where
+        P: Into<String>,
+    {
+        self.operation = Some(pattern.into());
+        self
+    }
+
+    /// Only matches spans started with `tag`.
+    pub fn tag(mut self, tag: Tag) -> Self {
+        self.tags.push(tag);
+        self
+    }
+
+    fn matches(&self, operation: &str, tags: &[Tag]) -> bool {
+        self.operation
+            .as_ref()
+            .map_or(true, |pattern| glob_match(pattern, operation))
+            && self.tags.iter().all(|expected| {
+                tags.iter()
+                    .any(|tag| tag.name() == expected.name() && tag.value() == expected.value())
+            })
+    }
+}
+
+/// Samples spans with the sampler of the first rule they match, or the
+/// default sampler if none does.
+///
+/// Rules are matched against the tags known at each step: the tags a span
+/// starts with, the tag being set, then all tags once the span finishes.
+/// Children of a trace whose sampling is final, such as one extracted with a
+/// sampled flag, follow that decision without consulting the rules.
+///
+/// Rules can be loaded from a TOML or JSON document:
+///
+/// ```toml
+/// [[rules]]
+/// operation = "/checkout*"
+/// sampler = { type = "const", decision = true }
+///
+/// [[rules]]
+/// operation = "/health"
+/// tags = { "http.method" = "GET" }
+/// sampler = { type = "probabilistic", rate = 0.01 }
+///
+/// [default]
+/// type = "ratelimiting"
+/// max_traces_per_second = 10.0
+/// ```
+pub struct RuleSampler {
+    rules: Vec<SamplingRule>,
+    default_sampler: Box<dyn Sampler>,
+}
+
+impl RuleSampler {
+    pub fn new<S>(default_sampler: S) -> Self
+    where
+        S: Sampler + 'static,
+    {
+        Self {
+            rules: Vec::new(),
+            default_sampler: Box::new(default_sampler),
+        }
+    }
+
+    pub fn rule(mut self, rule: SamplingRule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn from_toml(document: &str) -> Result<Self, RuleSamplerError> {
+        toml::from_str::<RuleSamplerConfig>(document)
+            .map_err(RuleSamplerError::Toml)?
+            .build()
+    }
+
+    pub fn from_json(document: &str) -> Result<Self, RuleSamplerError> {
+        serde_json::from_str::<RuleSamplerConfig>(document)
+            .map_err(RuleSamplerError::Json)?
+            .build()
+    }
+
+    /// Loads rules from a `.json` file, or a TOML file for any other
+    /// extension.
+    pub fn from_file<P>(path: P) -> Result<Self, RuleSamplerError>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let document = fs::read_to_string(path).map_err(RuleSamplerError::Io)?;
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Self::from_json(&document),
+            _ => Self::from_toml(&document),
+        }
+    }
+
+    fn sampler_for(&self, operation: &str, tags: &[Tag]) -> &dyn Sampler {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(operation, tags))
+            .map_or(self.default_sampler.as_ref(), |rule| rule.sampler.as_ref())
+    }
+}
+
+impl Sampler for RuleSampler {
+    fn is_sampled(&self, trace_id: &TraceId, operation: &str) -> (bool, Vec<Tag>) {
+        self.sampler_for(operation, &[])
+            .is_sampled(trace_id, operation)
+    }
+
+    fn on_create_span(&self, state: &SpanState, operation: &str, tags: &[Tag]) -> SamplingDecision {
+        self.sampler_for(operation, tags)
+            .on_create_span(state, operation, tags)
+    }
+
+    fn on_set_tag(&self, state: &SpanState, operation: &str, tag: &Tag) -> SamplingDecision {
+        self.sampler_for(operation, std::slice::from_ref(tag))
+            .on_set_tag(state, operation, tag)
+    }
+
+    fn on_finish_span(&self, state: &SpanState, operation: &str, tags: &[Tag]) -> SamplingDecision {
+        self.sampler_for(operation, tags)
+            .on_finish_span(state, operation, tags)
+    }
+
+    fn close(&self) {
+        for rule in &self.rules {
+            rule.sampler.close();
+        }
+        self.default_sampler.close();
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleSamplerConfig {
+    #[serde(default)]
+    rules: Vec<SamplingRuleConfig>,
+    default: SamplerConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SamplingRuleConfig {
+    operation: Option<String>,
+    #[serde(default)]
+    tags: BTreeMap<String, TagValueConfig>,
+    sampler: SamplerConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum SamplerConfig {
+    Const { decision: bool },
+    Probabilistic { rate: f64 },
+    RateLimiting { max_traces_per_second: f64 },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TagValueConfig {
+    Bool(bool),
+    Long(i64),
+    Double(f64),
+    String(String),
+}
+
+impl RuleSamplerConfig {
+    fn build(self) -> Result<RuleSampler, RuleSamplerError> {
+        let rules = self
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                rule.build().map_err(|reason| {
+                    RuleSamplerError::Invalid(format!("rule {}: {}", index, reason))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let default_sampler = self
+            .default
+            .build()
+            .map_err(|reason| RuleSamplerError::Invalid(format!("default sampler: {}", reason)))?;
+
+        Ok(RuleSampler {
+            rules,
+            default_sampler,
+        })
+    }
+}
+
+impl SamplingRuleConfig {
+    fn build(self) -> Result<SamplingRule, String> {
+        if let Some(operation) = &self.operation {
+            if operation.is_empty() {
+                return Err("operation pattern must not be empty".to_owned());
+            }
+        }
+
+        let tags = self
+            .tags
+            .into_iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    TagValueConfig::Bool(value) => TagValue::Bool(value),
+                    TagValueConfig::Long(value) => TagValue::Long(value),
+                    TagValueConfig::Double(value) => TagValue::Double(value),
+                    TagValueConfig::String(value) => TagValue::String(value),
+                };
+                Tag::new(name, value)
+            })
+            .collect();
+
+        Ok(SamplingRule {
+            operation: self.operation,
+            tags,
+            sampler: self.sampler.build()?,
+        })
+    }
+}
+
+impl SamplerConfig {
+    fn build(self) -> Result<Box<dyn Sampler>, String> {
+        let sampler: Box<dyn Sampler> = match self {
+            SamplerConfig::Const { decision } => Box::new(ConstSampler::new(decision)),
+            SamplerConfig::Probabilistic { rate } => {
+                Box::new(ProbabilisticSampler::new(rate).map_err(|err| err.to_string())?)
+            }
+            SamplerConfig::RateLimiting {
+                max_traces_per_second,
+            } => Box::new(
+                RateLimitingSampler::new(max_traces_per_second).map_err(|err| err.to_string())?,
+            ),
+        };
+        Ok(sampler)
+    }
+}
+
+/// Matches `text` against `pattern`, where `*` matches any sequence of
+/// characters and `?` any single character.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and of the text it was tried against.
+    let mut backtrack = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                Some((star, star_t)) => {
+                    p = star + 1;
+                    t = star_t + 1;
+                    backtrack = Some((star, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    /// Samples everything, tagging its decisions with its name and recording
+    /// the hooks called on it.
+    #[derive(Clone)]
+    struct NamedSampler {
+        name: &'static str,
+        calls: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl NamedSampler {
+        fn new(name: &'static str, calls: &Arc<Mutex<Vec<String>>>) -> Self {
+            Self {
+                name,
+                calls: calls.clone(),
+            }
+        }
+
+        fn record(&self, hook: &str) -> SamplingDecision {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{}.{}", self.name, hook));
+            SamplingDecision {
+                sample: true,
+                retryable: false,
+                tags: vec![Tag::new("sampler", self.name)],
+            }
+        }
+    }
+
+    impl Sampler for NamedSampler {
+        fn is_sampled(&self, _trace_id: &TraceId, _operation: &str) -> (bool, Vec<Tag>) {
+            (self.record("is_sampled").sample, Vec::new())
+        }
+
+        fn on_create_span(
+            &self,
+            _state: &SpanState,
+            _operation: &str,
+            _tags: &[Tag],
+        ) -> SamplingDecision {
+            self.record("on_create_span")
+        }
+
+        fn on_set_tag(&self, _state: &SpanState, _operation: &str, _tag: &Tag) -> SamplingDecision {
+            self.record("on_set_tag")
+        }
+
+        fn on_finish_span(
+            &self,
+            _state: &SpanState,
+            _operation: &str,
+            _tags: &[Tag],
+        ) -> SamplingDecision {
+            self.record("on_finish_span")
+        }
+
+        fn close(&self) {
+            self.record("close");
+        }
+    }
+
+    fn chosen(decision: SamplingDecision) -> String {
+        match decision.tags[0].value() {
+            TagValue::String(name) => name.clone(),
+            value => panic!("unexpected tag value {:?}", value),
+        }
+    }
+
+    fn root() -> SpanState {
+        SpanState::undecided(TraceId::new(), 1)
+    }
+
+    #[test]
+    fn matches_globs() {
+        for (pattern, text) in &[
+            ("", ""),
+            ("*", ""),
+            ("*", "/checkout"),
+            ("/checkout", "/checkout"),
+            ("/checkout*", "/checkout/cart"),
+            ("*/cart", "/checkout/cart"),
+            ("/c?eck*t", "/checkout"),
+            ("*a*b*", "xxaxxbxx"),
+            ("a*b*c", "abbbc"),
+            ("**", "anything"),
+            ("héllo?", "héllo!"),
+        ] {
+            assert!(
+                glob_match(pattern, text),
+                "{:?} should match {:?}",
+                pattern,
+                text
+            );
+        }
+
+        for (pattern, text) in &[
+            ("", "a"),
+            ("/checkout", "/checkout/cart"),
+            ("/checkout", "/check"),
+            ("?", ""),
+            ("a*b", "acbd"),
+            ("*a*b", "xxbxxa"),
+            ("/c?eck", "/cheeck"),
+        ] {
+            assert!(
+                !glob_match(pattern, text),
+                "{:?} should not match {:?}",
+                pattern,
+                text
+            );
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let sampler = RuleSampler::new(NamedSampler::new("default", &calls))
+            .rule(
+                SamplingRule::new(NamedSampler::new("get-checkout", &calls))
+                    .operation("/checkout*")
+                    .tag(Tag::new("http.method", "GET")),
+            )
+            .rule(SamplingRule::new(NamedSampler::new("checkout", &calls)).operation("/checkout*"))
+            .rule(SamplingRule::new(NamedSampler::new("catch-all", &calls)).operation("*"));
+        let get = [Tag::new("http.method", "GET"), Tag::new("other", 1)];
+        let post = [Tag::new("http.method", "POST")];
+
+        let decide = |operation: &str, tags: &[Tag]| {
+            chosen(sampler.on_create_span(&root(), operation, tags))
+        };
+        assert_eq!(decide("/checkout/cart", &get), "get-checkout");
+        assert_eq!(decide("/checkout/cart", &post), "checkout");
+        assert_eq!(decide("/checkout", &[]), "checkout");
+        assert_eq!(decide("/search", &get), "catch-all");
+
+        let sampler = RuleSampler::new(NamedSampler::new("default", &calls))
+            .rule(SamplingRule::new(NamedSampler::new("health", &calls)).operation("/health"));
+        assert_eq!(
+            chosen(sampler.on_create_span(&root(), "/search", &[])),
+            "default"
+        );
+    }
+
+    #[test]
+    fn forwards_hooks_to_the_matching_sampler() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let sampler = RuleSampler::new(NamedSampler::new("default", &calls))
+            .rule(
+                SamplingRule::new(NamedSampler::new("error", &calls)).tag(Tag::new("error", true)),
+            )
+            .rule(SamplingRule::new(NamedSampler::new("checkout", &calls)).operation("/checkout"));
+        let error = Tag::new("error", true);
+
+        sampler.on_set_tag(&root(), "op", &error);
+        sampler.on_set_tag(&root(), "op", &Tag::new("error", false));
+        sampler.on_set_tag(&root(), "/checkout", &Tag::new("error", false));
+        sampler.on_finish_span(&root(), "op", &[Tag::new("a", 1), error.clone()]);
+        sampler.on_finish_span(&root(), "op", &[]);
+        sampler.close();
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                "error.on_set_tag",
+                "default.on_set_tag",
+                "checkout.on_set_tag",
+                "error.on_finish_span",
+                "default.on_finish_span",
+                "error.close",
+                "checkout.close",
+                "default.close",
+            ]
+        );
+    }
+
+    #[test]
+    fn loads_rules_in_order() {
+        let sampler = RuleSampler::from_toml(
+            r#"
+            [[rules]]
+            operation = "/checkout*"
+            tags = { "http.method" = "GET" }
+            sampler = { type = "const", decision = true }
+
+            [[rules]]
+            operation = "/search"
+            sampler = { type = "const", decision = false }
+
+            [default]
+            type = "probabilistic"
+            rate = 1.0
+            "#,
+        )
+        .unwrap();
+        let get = [Tag::new("http.method", "GET")];
+
+        assert!(sampler.on_create_span(&root(), "/checkout", &get).sample);
+        assert!(!sampler.on_create_span(&root(), "/search", &get).sample);
+        assert!(sampler.on_create_span(&root(), "/cart", &get).sample);
+
+        let sampler = RuleSampler::from_json(
+            r#"{
+                "rules": [{"tags": {"debug": true}, "sampler": {"type": "const", "decision": false}}],
+                "default": {"type": "const", "decision": true}
+            }"#,
+        )
+        .unwrap();
+        let debug = [Tag::new("debug", true)];
+        assert!(!sampler.on_create_span(&root(), "op", &debug).sample);
+        assert!(sampler.on_create_span(&root(), "op", &[]).sample);
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        let invalid = [
+            r#"default = { type = "probabilistic", rate = 2.0 }"#,
+            r#"
+            [[rules]]
+            operation = ""
+            sampler = { type = "const", decision = true }
+            [default]
+            type = "const"
+            decision = true
+            "#,
+        ];
+        for document in &invalid {
+            assert!(matches!(
+                RuleSampler::from_toml(document),
+                Err(RuleSamplerError::Invalid(_))
+            ));
+        }
+
+        assert!(matches!(
+            RuleSampler::from_toml("[default]\ntype = \"const\"\nparent = true"),
+            Err(RuleSamplerError::Toml(_))
+        ));
+    }
+}
@@ -0,0 +1,69 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use futures::future;
+use futures::pin_mut;
+use futures::task::AtomicWaker;
+
+/// Stops background futures as soon as it is closed, rather than on their
+/// next tick.
+#[derive(Clone, Default)]
+pub(crate) struct CloseSignal {
+    inner: Arc<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    closed: AtomicBool,
+    waiters: Mutex<Vec<Arc<AtomicWaker>>>,
+}
+
+impl CloseSignal {
+    pub(crate) fn close(&self) {
+        self.inner.closed.store(true, Ordering::SeqCst);
+        for waiter in self.inner.waiters.lock().unwrap().iter() {
+            waiter.wake();
+        }
+    }
+
+    /// Runs `future` until it completes or the signal closes.
+    pub(crate) fn guard<F>(&self, future: F) -> impl Future<Output = ()>
+    where
+        F: Future<Output = ()>,
+    {
+        let waker = Arc::new(AtomicWaker::new());
+        self.inner.waiters.lock().unwrap().push(waker.clone());
+        let closed = Closed {
+            inner: self.inner.clone(),
+            waker,
+        };
+
+        async move {
+            pin_mut!(future);
+            future::select(future, closed).await;
+        }
+    }
+}
+
+struct Closed {
+    inner: Arc<Inner>,
+    waker: Arc<AtomicWaker>,
+}
+
+impl Future for Closed {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        // Registering first so that a racing `close` still wakes this task.
+        self.waker.register(cx.waker());
+
+        if self.inner.closed.load(Ordering::SeqCst) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
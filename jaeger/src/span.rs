@@ -1,16 +1,17 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use std::sync::Arc;
 use std::time::SystemTime;
 
-use futures::sync::mpsc;
+use opentracing_rs_core::{BaggageItem, SpanObserver, SpanSink, Tag, TagValue};
 
-use opentracing_rs_core::{BaggageItem, Tag};
-
-use crate::Sampler;
+use crate::{tag, DebugThrottler, Sampler, SamplingDecision};
 
 pub type Span = opentracing_rs_core::Span<SpanState>;
 pub type SpanReference = opentracing_rs_core::SpanReference<SpanState>;
+pub type SpanContext = opentracing_rs_core::SpanContext<SpanState>;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct TraceId {
     pub(crate) low: u64,
     pub(crate) high: u64,
@@ -23,6 +24,117 @@ impl TraceId {
             high: rand::random(),
         }
     }
+
+    /// Parses a hex encoded trace id of up to 128 bits.
+    pub(crate) fn from_hex(s: &str) -> Option<Self> {
+        if s.is_empty() || s.len() > 32 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        let (high, low) = if s.len() > 16 {
+            s.split_at(s.len() - 16)
+        } else {
+            ("", s)
+        };
+        let high = if high.is_empty() {
+            0
+        } else {
+            u64::from_str_radix(high, 16).ok()?
+        };
+        let low = u64::from_str_radix(low, 16).ok()?;
+
+        Some(Self { low, high })
+    }
+}
+
+impl Default for TraceId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.high == 0 {
+            write!(f, "{:016x}", self.low)
+        } else {
+            write!(f, "{:016x}{:016x}", self.high, self.low)
+        }
+    }
+}
+
+/// Jaeger trace flags, propagated with the span context and reported with
+/// every span.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SpanFlags(u8);
+
+impl SpanFlags {
+    pub const SAMPLED: u8 = 0x01;
+    /// Forces sampling, set when the trace was requested for debugging.
+    pub const DEBUG: u8 = 0x02;
+    /// Asks the backend to skip indexing the trace.
+    pub const FIREHOSE: u8 = 0x08;
+
+    pub fn from_bits(bits: u8) -> Self {
+        SpanFlags(bits)
+    }
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub fn is_sampled(self) -> bool {
+        self.0 & Self::SAMPLED != 0
+    }
+
+    pub fn is_debug(self) -> bool {
+        self.0 & Self::DEBUG != 0
+    }
+
+    pub fn is_firehose(self) -> bool {
+        self.0 & Self::FIREHOSE != 0
+    }
+
+    pub fn set_sampled(&mut self, sampled: bool) {
+        self.set(Self::SAMPLED, sampled);
+    }
+
+    /// Setting the debug flag also sets the sampled flag.
+    pub fn set_debug(&mut self, debug: bool) {
+        self.set(Self::DEBUG, debug);
+        if debug {
+            self.set(Self::SAMPLED, true);
+        }
+    }
+
+    pub fn set_firehose(&mut self, firehose: bool) {
+        self.set(Self::FIREHOSE, firehose);
+    }
+
+    fn set(&mut self, flag: u8, value: bool) {
+        if value {
+            self.0 |= flag;
+        } else {
+            self.0 &= !flag;
+        }
+    }
+}
+
+/// Flags shared by the spans of a trace in this process, so that a sampling
+/// decision made on any of them applies to all. Once final, samplers are no
+/// longer consulted.
+#[derive(Debug)]
+struct SamplingState {
+    flags: AtomicU8,
+    is_final: AtomicBool,
+}
+
+impl SamplingState {
+    fn new(flags: SpanFlags, is_final: bool) -> Self {
+        Self {
+            flags: AtomicU8::new(flags.bits()),
+            is_final: AtomicBool::new(is_final),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -30,25 +142,145 @@ pub struct SpanState {
     pub(crate) trace_id: TraceId,
     pub(crate) span_id: u64,
     pub(crate) parent_span_id: Option<u64>,
-    pub(crate) is_sampled: bool,
+    sampling: Arc<SamplingState>,
+    /// Opaque W3C `tracestate` of the trace, if it was extracted from one.
+    pub(crate) trace_state: Option<String>,
+    /// Value of an extracted `jaeger-debug-id` carrier entry.
+    pub(crate) debug_id: Option<String>,
+    /// Whether the span was started by this tracer, rather than extracted.
+    local: bool,
+    /// Whether the span was started by this tracer without a local parent.
+    local_root: bool,
 }
 
 impl SpanState {
+    /// Creates the state of a trace whose sampling is already decided.
     pub fn new(trace_id: TraceId, span_id: u64, is_sampled: bool) -> Self {
+        let mut flags = SpanFlags::default();
+        flags.set_sampled(is_sampled);
+
         Self {
             trace_id,
             span_id,
-            is_sampled,
+            sampling: Arc::new(SamplingState::new(flags, true)),
             parent_span_id: None,
+            trace_state: None,
+            debug_id: None,
+            local: false,
+            local_root: false,
         }
     }
 
+    /// Creates the state of a trace the sampler has yet to decide on, either a
+    /// new one or one whose caller deferred the decision.
+    pub(crate) fn undecided(trace_id: TraceId, span_id: u64) -> Self {
+        let mut state = Self::new(trace_id, span_id, false);
+        state.sampling = Arc::new(SamplingState::new(SpanFlags::default(), false));
+        state
+    }
+
+    /// State extracted from a carrier holding only a `jaeger-debug-id`,
+    /// spans started from it begin a new debug trace.
+    pub(crate) fn debug_id_container(debug_id: String) -> Self {
+        let mut state = Self::new(TraceId { low: 0, high: 0 }, 0, false);
+        state.debug_id = Some(debug_id);
+        state
+    }
+
+    pub(crate) fn is_debug_id_container(&self) -> bool {
+        self.debug_id.is_some() && self.trace_id.low == 0 && self.trace_id.high == 0
+    }
+
+    /// Creates the state of a child span, sharing the sampling state of its
+    /// parent.
     pub fn from_parent(parent: Self) -> Self {
         Self {
             trace_id: parent.trace_id,
             span_id: rand::random(),
             parent_span_id: Some(parent.span_id),
-            is_sampled: parent.is_sampled,
+            sampling: parent.sampling,
+            trace_state: parent.trace_state,
+            debug_id: None,
+            local: false,
+            local_root: false,
+        }
+    }
+
+    pub fn trace_id(&self) -> &TraceId {
+        &self.trace_id
+    }
+
+    pub fn span_id(&self) -> u64 {
+        self.span_id
+    }
+
+    pub fn parent_span_id(&self) -> Option<u64> {
+        self.parent_span_id
+    }
+
+    pub fn flags(&self) -> SpanFlags {
+        SpanFlags::from_bits(self.sampling.flags.load(Ordering::SeqCst))
+    }
+
+    pub fn is_sampled(&self) -> bool {
+        self.flags().is_sampled()
+    }
+
+    pub fn is_debug(&self) -> bool {
+        self.flags().is_debug()
+    }
+
+    /// Whether this span started the local part of its trace, either as the
+    /// root of the trace or as the child of an extracted context.
+    pub fn is_local_root(&self) -> bool {
+        self.local_root
+    }
+
+    /// Whether the sampling decision of the trace can no longer change.
+    pub fn is_sampling_final(&self) -> bool {
+        self.sampling.is_final.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn set_flags(&self, flags: SpanFlags) {
+        self.sampling.flags.store(flags.bits(), Ordering::SeqCst);
+    }
+
+    pub(crate) fn update_flags<F>(&self, update: F)
+    where
+        F: Fn(&mut SpanFlags),
+    {
+        let _ = self
+            .sampling
+            .flags
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |bits| {
+                let mut flags = SpanFlags::from_bits(bits);
+                update(&mut flags);
+                Some(flags.bits())
+            });
+    }
+
+    pub(crate) fn finalize_sampling(&self) {
+        self.sampling.is_final.store(true, Ordering::SeqCst);
+    }
+
+    /// Applies `decision` unless the trace's sampling is already final,
+    /// returning the sampler tags to record if the trace got sampled.
+    fn apply_sampling_decision(&self, decision: SamplingDecision) -> Vec<Tag> {
+        if self.is_sampling_final() {
+            return Vec::new();
+        }
+
+        if decision.sample {
+            self.update_flags(|flags| flags.set_sampled(true));
+        }
+        if !decision.retryable {
+            self.finalize_sampling();
+        }
+
+        if decision.sample {
+            decision.tags
+        } else {
+            Vec::new()
         }
     }
 }
@@ -59,21 +291,118 @@ impl Default for SpanState {
     }
 }
 
+/// Applies sampling related changes to the spans of a tracer: the
+/// `sampling.priority` tag, and the decisions of a sampler that deferred them
+/// when tags get set or spans finish.
+pub(crate) struct SamplingObserver {
+    sampler: Arc<dyn Sampler>,
+    debug_throttler: Option<Arc<dyn DebugThrottler>>,
+}
+
+impl SamplingObserver {
+    pub(crate) fn new(
+        sampler: Arc<dyn Sampler>,
+        debug_throttler: Option<Arc<dyn DebugThrottler>>,
+    ) -> Self {
+        Self {
+            sampler,
+            debug_throttler,
+        }
+    }
+
+    fn is_debug_allowed(&self, operation: &str) -> bool {
+        self.debug_throttler
+            .as_ref()
+            .map_or(true, |throttler| throttler.is_allowed(operation))
+    }
+
+    /// Zero stops sampling, a positive value starts a debug trace. Either way
+    /// the decision becomes final.
+    fn apply_sampling_priority(&self, state: &SpanState, operation: &str, tag: &Tag) {
+        match sampling_priority(tag.value()) {
+            Some(0.0) => {
+                state.update_flags(|flags| {
+                    flags.set_debug(false);
+                    flags.set_sampled(false);
+                });
+                state.finalize_sampling();
+            }
+            Some(priority) if priority > 0.0 && self.is_debug_allowed(operation) => {
+                state.update_flags(|flags| flags.set_debug(true));
+                state.finalize_sampling();
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Reads a `sampling.priority` value, set as a number, a bool, or a string
+/// holding either.
+fn sampling_priority(value: &TagValue) -> Option<f64> {
+    match value {
+        TagValue::Long(priority) => Some(*priority as f64),
+        TagValue::Double(priority) => Some(*priority),
+        TagValue::Bool(priority) => Some(if *priority { 1.0 } else { 0.0 }),
+        TagValue::String(priority) => match priority.trim() {
+            "true" => Some(1.0),
+            "false" => Some(0.0),
+            priority => priority.parse().ok(),
+        },
+        TagValue::Binary(_) => None,
+    }
+}
+
+impl SpanObserver<SpanState> for SamplingObserver {
+    fn on_set_tag(&self, span: &mut Span, tag: &Tag) {
+        let state = span.context().state();
+        if tag.name() == tag::SAMPLING_PRIORITY_TAG_KEY {
+            self.apply_sampling_priority(state, span.operation_name(), tag);
+            return;
+        }
+        if state.is_sampling_final() {
+            return;
+        }
+
+        let decision = self.sampler.on_set_tag(state, span.operation_name(), tag);
+        let sampler_tags = state.apply_sampling_decision(decision);
+        for tag in sampler_tags {
+            span.set_tag(tag);
+        }
+    }
+
+    fn on_finish(&self, span: &mut Span) {
+        let state = span.context().state();
+        if state.is_sampling_final() {
+            return;
+        }
+
+        let decision = self
+            .sampler
+            .on_finish_span(state, span.operation_name(), span.tags());
+        let sampler_tags = state.apply_sampling_decision(decision);
+        for tag in sampler_tags {
+            span.set_tag(tag);
+        }
+    }
+}
+
 pub struct SpanBuilder {
-    sender: mpsc::UnboundedSender<Span>,
+    sink: Arc<dyn SpanSink<SpanState>>,
     operation_name: String,
     start_time: Option<SystemTime>,
     tags: Vec<Tag>,
     references: Vec<SpanReference>,
     baggage_items: Vec<BaggageItem>,
-    sampler: Arc<Sampler>,
+    sampler: Arc<dyn Sampler>,
+    observer: Arc<SamplingObserver>,
 }
 
 impl SpanBuilder {
-    pub fn new<N>(
+    pub(crate) fn new<N>(
         operation_name: N,
-        sampler: Arc<Sampler>,
-        sender: mpsc::UnboundedSender<Span>,
+        sampler: Arc<dyn Sampler>,
+        observer: Arc<SamplingObserver>,
+        sink: Arc<dyn SpanSink<SpanState>>,
     ) -> Self
     where
         N: Into<String>,
@@ -83,12 +412,13 @@ impl SpanBuilder {
         let tags = Vec::new();
         let references = Vec::new();
         Self {
-            sender,
+            sink,
             operation_name,
             baggage_items,
             tags,
             references,
             sampler,
+            observer,
             start_time: None,
         }
     }
@@ -105,46 +435,99 @@ impl opentracing_rs_core::SpanBuilder<SpanState> for SpanBuilder {
         self
     }
 
-    fn child_of(mut self, span: &Span) -> Self {
-        self.baggage_items
-            .extend(span.context().baggage_items().clone());
-        self.references
-            .push(opentracing_rs_core::SpanReference::ChildOf(
-                span.context().state().clone(),
-            ));
+    fn add_reference(
+        mut self,
+        reference: opentracing_rs_core::SpanReference<&SpanContext>,
+    ) -> Self {
+        let (context, reference) = match reference {
+            opentracing_rs_core::SpanReference::ChildOf(context) => {
+                (context, SpanReference::ChildOf(context.state().clone()))
+            }
+            opentracing_rs_core::SpanReference::FollowsFrom(context) => {
+                (context, SpanReference::FollowsFrom(context.state().clone()))
+            }
+        };
+
+        self.baggage_items.extend(
+            context
+                .baggage_items()
+                .map(|(key, value)| BaggageItem::new(key, value)),
+        );
+        self.references.push(reference);
         self
     }
 
     fn start(mut self) -> Span {
-        let state = {
-            let mut state = None;
-
-            for reference in &self.references {
-                match reference {
-                    opentracing_rs_core::SpanReference::ChildOf(parent) => {
-                        state = Some(SpanState::from_parent(parent.clone()))
-                    }
-                    _ => {}
-                }
+        // A context holding only a debug id is not a parent, it asks for a new
+        // debug trace instead.
+        let mut debug_id = None;
+        self.references.retain(|reference| {
+            let state = match reference {
+                SpanReference::ChildOf(state) | SpanReference::FollowsFrom(state) => state,
+            };
+            if !state.is_debug_id_container() {
+                return true;
+            }
+            if debug_id.is_none() {
+                debug_id = state.debug_id.clone();
             }
-            match state {
-                Some(state) => state,
-                None => {
-                    let trace_id = TraceId::new();
-                    let span_id = rand::random();
-                    let (is_sampled, tags) =
-                        self.sampler.is_sampled(&trace_id, &self.operation_name);
-
-                    self.tags.extend_from_slice(tags);
-
-                    let state = SpanState::new(trace_id, span_id, is_sampled);
-                    state
-                }
+            false
+        });
+
+        // The first ChildOf reference is the parent, falling back to the first
+        // FollowsFrom reference. Every reference is still reported, and the
+        // parent's flags, remote or not, decide sampling for the whole trace.
+        let parent = self
+            .references
+            .iter()
+            .find_map(|reference| match reference {
+                SpanReference::ChildOf(parent) => Some(parent),
+                _ => None,
+            })
+            .or_else(|| {
+                self.references
+                    .iter()
+                    .find_map(|reference| match reference {
+                        SpanReference::FollowsFrom(parent) => Some(parent),
+                        _ => None,
+                    })
+            });
+
+        let local_root = parent.map_or(true, |parent| !parent.local);
+        let mut state = match (parent, debug_id) {
+            (Some(parent), _) => SpanState::from_parent(parent.clone()),
+            (None, Some(debug_id)) if self.observer.is_debug_allowed(&self.operation_name) => {
+                let state = SpanState::new(TraceId::new(), rand::random(), true);
+                state.update_flags(|flags| flags.set_debug(true));
+                self.tags
+                    .push(Tag::new(tag::JAEGER_DEBUG_ID_TAG_KEY, debug_id));
+
+                state
             }
+            (None, _) => SpanState::undecided(TraceId::new(), rand::random()),
         };
+        state.local = true;
+        state.local_root = local_root;
+
+        // Samplers may defer their decision, in which case every span of the
+        // trace gets a say until it is final.
+        if !state.is_sampling_final() {
+            let decision = self
+                .sampler
+                .on_create_span(&state, &self.operation_name, &self.tags);
+            let sampler_tags = state.apply_sampling_decision(decision);
+            self.tags.extend(sampler_tags);
+        }
+
+        for tag in &self.tags {
+            if tag.name() == tag::SAMPLING_PRIORITY_TAG_KEY {
+                self.observer
+                    .apply_sampling_priority(&state, &self.operation_name, tag);
+            }
+        }
 
         Span::new(
-            self.sender,
+            self.sink,
             self.operation_name,
             self.start_time.unwrap_or_else(SystemTime::now),
             self.tags,
@@ -152,5 +535,238 @@ impl opentracing_rs_core::SpanBuilder<SpanState> for SpanBuilder {
             state,
             self.baggage_items,
         )
+        .with_observer(self.observer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentracing_rs_core::SpanBuilder as _;
+
+    use super::*;
+    use crate::testing::SpanRecorder;
+    use crate::{ConstSampler, RateLimitingDebugThrottler};
+
+    fn observer(debug_throttler: Option<Arc<dyn DebugThrottler>>) -> SamplingObserver {
+        SamplingObserver::new(Arc::new(ConstSampler::new(false)), debug_throttler)
+    }
+
+    fn apply(observer: &SamplingObserver, state: &SpanState, priority: TagValue) {
+        let tag = Tag::new(tag::SAMPLING_PRIORITY_TAG_KEY, priority);
+        observer.apply_sampling_priority(state, "op", &tag);
+    }
+
+    #[test]
+    fn positive_priority_starts_debug_trace() {
+        let observer = observer(None);
+        for priority in [
+            TagValue::Long(1),
+            TagValue::Double(0.5),
+            TagValue::Bool(true),
+            TagValue::String("1".to_owned()),
+            TagValue::String(" 2.5 ".to_owned()),
+            TagValue::String("true".to_owned()),
+        ] {
+            let state = SpanState::undecided(TraceId::new(), 1);
+            apply(&observer, &state, priority.clone());
+            assert!(state.is_debug(), "not debug for {:?}", priority);
+            assert!(state.is_sampled());
+            assert!(state.is_sampling_final());
+        }
+    }
+
+    #[test]
+    fn zero_priority_stops_sampling() {
+        let observer = observer(None);
+        for priority in [
+            TagValue::Long(0),
+            TagValue::Double(0.0),
+            TagValue::Bool(false),
+            TagValue::String("0".to_owned()),
+            TagValue::String("false".to_owned()),
+        ] {
+            let state = SpanState::new(TraceId::new(), 1, true);
+            state.update_flags(|flags| flags.set_debug(true));
+            apply(&observer, &state, priority.clone());
+            assert!(!state.is_sampled(), "still sampled for {:?}", priority);
+            assert!(!state.is_debug());
+            assert!(state.is_sampling_final());
+        }
+    }
+
+    #[test]
+    fn ignores_other_priorities() {
+        let observer = observer(None);
+        for priority in [
+            TagValue::Long(-1),
+            TagValue::Double(f64::NAN),
+            TagValue::String("high".to_owned()),
+            TagValue::Binary(vec![1]),
+        ] {
+            let state = SpanState::undecided(TraceId::new(), 1);
+            apply(&observer, &state, priority.clone());
+            assert!(!state.is_sampled(), "sampled for {:?}", priority);
+            assert!(!state.is_sampling_final());
+        }
+    }
+
+    #[test]
+    fn throttles_debug_traces() {
+        let throttler = RateLimitingDebugThrottler::new(1.0).unwrap();
+        let observer = observer(Some(Arc::new(throttler)));
+
+        let state = SpanState::undecided(TraceId::new(), 1);
+        apply(&observer, &state, TagValue::Long(1));
+        assert!(state.is_debug());
+
+        let state = SpanState::undecided(TraceId::new(), 1);
+        apply(&observer, &state, TagValue::Long(1));
+        assert!(!state.is_debug());
+        assert!(!state.is_sampling_final());
+    }
+
+    fn reference_ids(span: &Span) -> Vec<(&'static str, u64)> {
+        span.references()
+            .iter()
+            .map(|reference| match reference {
+                SpanReference::ChildOf(state) => ("child_of", state.span_id()),
+                SpanReference::FollowsFrom(state) => ("follows_from", state.span_id()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn follows_from_context_is_parent_without_child_of() {
+        let spans = SpanRecorder::default();
+        let producer = spans.span("produce").start();
+
+        let consumer = spans
+            .span("consume")
+            .follows_from(producer.context())
+            .start();
+        let state = consumer.context().state();
+        assert_eq!(state.trace_id(), producer.context().state().trace_id());
+        assert_eq!(
+            state.parent_span_id(),
+            Some(producer.context().state().span_id())
+        );
+    }
+
+    #[test]
+    fn child_of_wins_over_earlier_follows_from() {
+        let spans = SpanRecorder::default();
+        let previous = spans.span("previous").start();
+        let parent = spans.span("parent").start();
+
+        let span = spans
+            .span("op")
+            .follows_from(previous.context())
+            .child_of(parent.context())
+            .start();
+        let state = span.context().state();
+        assert_eq!(state.trace_id(), parent.context().state().trace_id());
+        assert_eq!(
+            state.parent_span_id(),
+            Some(parent.context().state().span_id())
+        );
+    }
+
+    #[test]
+    fn reports_every_reference() {
+        let spans = SpanRecorder::default();
+        let first = spans.span("first").start();
+        let second = spans.span("second").start();
+        let third = spans.span("third").start();
+
+        drop(
+            spans
+                .span("op")
+                .follows_from(first.context())
+                .child_of(second.context())
+                .follows_from(third.context())
+                .start(),
+        );
+
+        let finished = spans.finished();
+        assert_eq!(
+            reference_ids(&finished[0]),
+            [
+                ("follows_from", first.context().state().span_id()),
+                ("child_of", second.context().state().span_id()),
+                ("follows_from", third.context().state().span_id()),
+            ]
+        );
+    }
+
+    #[test]
+    fn inherits_baggage_of_references() {
+        let spans = SpanRecorder::default();
+        let mut parent = spans.span("parent").start();
+        parent.set_baggage_item("tenant", "acme");
+        let mut previous = spans.span("previous").start();
+        previous.set_baggage_item("batch", "7");
+
+        let child = spans
+            .span("child")
+            .follows_from(previous.context())
+            .child_of(parent.context())
+            .start();
+        assert_eq!(child.baggage_item("tenant"), Some("acme"));
+        assert_eq!(child.baggage_item("batch"), Some("7"));
+
+        let grandchild = spans.span("grandchild").child_of(child.context()).start();
+        assert_eq!(grandchild.baggage_item("tenant"), Some("acme"));
+    }
+
+    fn remote_context(flags: u8) -> SpanContext {
+        let state = SpanState::new(TraceId::new(), 1, false);
+        state.set_flags(SpanFlags::from_bits(flags));
+        SpanContext::new(state, Vec::new())
+    }
+
+    #[test]
+    fn children_inherit_flags() {
+        let spans = SpanRecorder::default();
+        let flags = SpanFlags::SAMPLED | SpanFlags::DEBUG | SpanFlags::FIREHOSE;
+        let remote = remote_context(flags);
+
+        let child = spans.span("child").child_of(&remote).start();
+        let grandchild = spans.span("grandchild").child_of(child.context()).start();
+
+        assert_eq!(child.context().state().flags().bits(), flags);
+        assert_eq!(grandchild.context().state().flags().bits(), flags);
+        assert!(grandchild.context().state().flags().is_firehose());
+    }
+
+    #[test]
+    fn remote_sampling_flags_are_final() {
+        let sampling_everything = SpanRecorder::with_sampler(ConstSampler::new(true));
+        let span = sampling_everything
+            .span("op")
+            .child_of(&remote_context(0))
+            .start();
+        assert!(!span.context().state().is_sampled());
+        assert!(span.context().state().is_sampling_final());
+
+        let sampling_nothing = SpanRecorder::default();
+        for flags in [SpanFlags::SAMPLED, SpanFlags::DEBUG | SpanFlags::SAMPLED] {
+            let span = sampling_nothing
+                .span("op")
+                .child_of(&remote_context(flags))
+                .start();
+            assert!(span.context().state().is_sampled());
+            assert_eq!(span.context().state().flags().bits(), flags);
+        }
+    }
+
+    #[test]
+    fn encodes_flags() {
+        let spans = SpanRecorder::default();
+        let flags = SpanFlags::SAMPLED | SpanFlags::DEBUG;
+
+        drop(spans.span("op").child_of(&remote_context(flags)).start());
+
+        let span = crate::thrift_gen::jaeger::Span::from(spans.finished().remove(0));
+        assert_eq!(span.flags, i32::from(flags));
     }
 }
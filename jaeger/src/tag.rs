@@ -1,9 +1,16 @@
 pub const JAEGER_CLIENT_VERSION_TAG_KEY: &str = "jaeger.version";
 pub const JAEGER_CLIENT_VERSION: &str = concat!("opentracing-rs-", env!("CARGO_PKG_VERSION"));
 
+pub const JAEGER_DEBUG_ID_TAG_KEY: &str = "jaeger-debug-id";
+pub const SAMPLING_PRIORITY_TAG_KEY: &str = "sampling.priority";
+
+pub const ERROR_TAG_KEY: &str = "error";
+
 pub const SAMPLER_TYPE_TAG_KEY: &str = "sampler.type";
 pub const SAMPLER_PARAM_TAG_KEY: &str = "sampler.param";
 pub const SAMPLER_TYPE_CONST: &str = "const";
 
-pub const SAMPLER_TYPE_REMOTE: &str = "remote";
 pub const SAMPLER_TYPE_PROBABILISTIC: &str = "probabilistic";
+pub const SAMPLER_TYPE_RATE_LIMITING: &str = "ratelimiting";
+pub const SAMPLER_TYPE_LOWER_BOUND: &str = "lowerbound";
+pub const SAMPLER_TYPE_DEFERRED: &str = "deferred";
@@ -0,0 +1,133 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use opentracing_rs_core::{SpanSink, Tag};
+
+use crate::{
+    span::SamplingObserver, ConstSampler, Error, ErrorHandler, Reporter, Sampler, Span,
+    SpanBuilder, SpanState,
+};
+
+/// Keeps the spans submitted to it.
+#[derive(Default)]
+pub(crate) struct CollectingSink {
+    pub(crate) spans: Mutex<Vec<Span>>,
+}
+
+impl SpanSink<SpanState> for CollectingSink {
+    fn submit(&self, span: Span) {
+        self.spans.lock().unwrap().push(span);
+    }
+}
+
+/// Builds spans the way a tracer does, sampling nothing by default, and keeps
+/// them once finished.
+pub(crate) struct SpanRecorder {
+    sink: Arc<CollectingSink>,
+    sampler: Arc<dyn Sampler>,
+    observer: Arc<SamplingObserver>,
+}
+
+impl Default for SpanRecorder {
+    fn default() -> Self {
+        Self::with_sampler(ConstSampler::new(false))
+    }
+}
+
+impl SpanRecorder {
+    pub(crate) fn with_sampler<S>(sampler: S) -> Self
+    where
+        S: Sampler + 'static,
+    {
+        let sampler: Arc<dyn Sampler> = Arc::new(sampler);
+        Self {
+            sink: Arc::new(CollectingSink::default()),
+            observer: Arc::new(SamplingObserver::new(sampler.clone(), None)),
+            sampler,
+        }
+    }
+
+    pub(crate) fn span(&self, operation_name: &str) -> SpanBuilder {
+        SpanBuilder::new(
+            operation_name,
+            self.sampler.clone(),
+            self.observer.clone(),
+            self.sink.clone(),
+        )
+    }
+
+    /// Takes the spans finished so far.
+    pub(crate) fn finished(&self) -> Vec<Span> {
+        self.sink.spans.lock().unwrap().drain(..).collect()
+    }
+}
+
+/// Starts a span at `start_time` and finishes it right away.
+pub(crate) fn finished_span_at(
+    operation_name: &str,
+    state: SpanState,
+    start_time: SystemTime,
+    tags: Vec<Tag>,
+) -> Span {
+    let sink = Arc::new(CollectingSink::default());
+    let mut span = Span::new(
+        sink.clone(),
+        operation_name,
+        start_time,
+        tags,
+        Vec::new(),
+        state,
+        Vec::new(),
+    );
+    span.finish();
+
+    let span = sink.spans.lock().unwrap().pop().unwrap();
+    span
+}
+
+pub(crate) fn finished_span(operation_name: &str, state: SpanState, tags: Vec<Tag>) -> Span {
+    finished_span_at(operation_name, state, SystemTime::now(), tags)
+}
+
+/// Error handler keeping the errors it is given.
+pub(crate) fn collecting_error_handler() -> (impl ErrorHandler, Arc<Mutex<Vec<Error>>>) {
+    let errors = Arc::new(Mutex::new(Vec::new()));
+    let handler = {
+        let errors = errors.clone();
+        move |err| errors.lock().unwrap().push(err)
+    };
+    (handler, errors)
+}
+
+/// Keeps the spans reported to it.
+#[derive(Clone, Default)]
+pub(crate) struct RecordingReporter {
+    pub(crate) spans: Arc<Mutex<Vec<Span>>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl RecordingReporter {
+    pub(crate) fn operations(&self) -> Vec<String> {
+        self.spans
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|span| span.operation_name().to_owned())
+            .collect()
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+}
+
+impl Reporter for RecordingReporter {
+    fn report(&mut self, span: Span) {
+        self.spans.lock().unwrap().push(span);
+    }
+
+    fn close(&mut self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+}
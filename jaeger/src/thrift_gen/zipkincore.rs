@@ -3,7 +3,7 @@
 
 #![allow(unused_imports)]
 #![allow(unused_extern_crates)]
-#![cfg_attr(feature = "cargo-clippy", allow(too_many_arguments, type_complexity))]
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
 
 #![cfg_attr(rustfmt, rustfmt_skip)]
 
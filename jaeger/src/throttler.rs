@@ -0,0 +1,64 @@
+use std::sync::Mutex;
+
+use crate::{sampler::RateLimiter, Error, Result};
+
+/// Decides whether an operation may start a debug trace, either through a
+/// `jaeger-debug-id` carrier entry or a positive `sampling.priority` tag.
+pub trait DebugThrottler: Send + Sync {
+    fn is_allowed(&self, operation: &str) -> bool;
+}
+
+/// Allows at most `max_debug_traces_per_second` debug traces across all
+/// operations.
+pub struct RateLimitingDebugThrottler {
+    rate_limiter: Mutex<RateLimiter>,
+}
+
+impl RateLimitingDebugThrottler {
+    /// Fails unless `max_debug_traces_per_second` is positive.
+    pub fn new(max_debug_traces_per_second: f64) -> Result<Self> {
+        if max_debug_traces_per_second <= 0.0 || max_debug_traces_per_second.is_nan() {
+            return Err(Error::InvalidRateLimit(max_debug_traces_per_second));
+        }
+
+        Ok(Self {
+            rate_limiter: Mutex::new(RateLimiter::new(
+                max_debug_traces_per_second,
+                max_debug_traces_per_second.max(1.0),
+            )),
+        })
+    }
+}
+
+impl DebugThrottler for RateLimitingDebugThrottler {
+    fn is_allowed(&self, _operation: &str) -> bool {
+        self.rate_limiter.lock().unwrap().check_credit(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_rates() {
+        for rate in &[0.0, -1.0, f64::NAN, f64::NEG_INFINITY] {
+            match RateLimitingDebugThrottler::new(*rate) {
+                Err(Error::InvalidRateLimit(_)) => {}
+                _ => panic!("accepted rate {}", rate),
+            }
+        }
+    }
+
+    #[test]
+    fn allows_bursts_up_to_the_rate() {
+        let throttler = RateLimitingDebugThrottler::new(3.0).unwrap();
+        let allowed = (0..10).filter(|_| throttler.is_allowed("op")).count();
+        assert_eq!(allowed, 3);
+
+        // Below one trace per second, a single trace is still allowed.
+        let throttler = RateLimitingDebugThrottler::new(0.1).unwrap();
+        assert!(throttler.is_allowed("op"));
+        assert!(!throttler.is_allowed("op"));
+    }
+}
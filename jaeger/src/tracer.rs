@@ -1,12 +1,29 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::thread;
 use std::time::Duration;
 
-use futures::{sync::mpsc, Future, Stream};
+use futures::channel::oneshot;
+use futures::future::{self, BoxFuture, FutureExt, Shared};
+use futures::{Future, StreamExt};
+#[cfg(feature = "tokio")]
+use hyper::Uri;
 
-use opentracing_rs_core::Tag;
+use opentracing_rs_core::{
+    ExtractFormat, Format, InjectFormat, PropagationError, Tag, TextMapReader,
+};
 
-use crate::{Reporter, Sampler, Span, SpanBuilder, SpanState, TransportProtocol};
+use crate::{
+    error::ForwardingErrorHandler,
+    propagation,
+    queue::{BoundedQueue, DropPolicy},
+    runtime,
+    span::SamplingObserver,
+    DebugThrottler, Error, ErrorHandler, JaegerPropagator, Reporter, Sampler, Span, SpanBuilder,
+    SpanContext, SpanState, TailSamplingPolicy, TextMapPropagator, Transport, TransportProtocol,
+};
+
+const DEBUG_ID_HEADER: &str = "jaeger-debug-id";
 
 #[derive(Clone)]
 pub struct Process {
@@ -42,30 +59,101 @@ impl Process {
     }
 }
 
+/// Number of finished spans queued for the reporter by default.
+pub const DEFAULT_SPAN_QUEUE_CAPACITY: usize = 10_000;
+
 #[derive(Clone)]
 pub struct Tracer {
-    sampler: Arc<Sampler>,
-    sender: mpsc::UnboundedSender<Span>,
+    sampler: Arc<dyn Sampler>,
+    span_queue: Arc<BoundedQueue<Span>>,
+    /// Resolves once the reporter shut down after the span queue closed.
+    closed: Shared<oneshot::Receiver<()>>,
+    observer: Arc<SamplingObserver>,
+    text_map_propagator: Arc<dyn TextMapPropagator>,
+    http_headers_propagator: Arc<dyn TextMapPropagator>,
 }
 
 impl Tracer {
     pub fn new(
-        sampler: Arc<Sampler>,
-        mut reporter: Box<Reporter>,
-    ) -> (Self, impl Future<Item = (), Error = ()>) {
-        let (sender, receiver) = mpsc::unbounded();
-
-        let tracer = Self { sampler, sender };
-        let serve = receiver
-            .for_each(move |span| {
+        sampler: Arc<dyn Sampler>,
+        reporter: Box<dyn Reporter>,
+    ) -> (Self, impl Future<Output = ()>) {
+        Self::with_span_queue(
+            sampler,
+            reporter,
+            DEFAULT_SPAN_QUEUE_CAPACITY,
+            DropPolicy::default(),
+        )
+    }
+
+    /// Creates a tracer queueing at most `capacity` finished spans for the
+    /// reporter, `policy` decides which span to drop once the queue is full.
+    /// Finishing a span never blocks.
+    pub fn with_span_queue(
+        sampler: Arc<dyn Sampler>,
+        mut reporter: Box<dyn Reporter>,
+        capacity: usize,
+        policy: DropPolicy,
+    ) -> (Self, impl Future<Output = ()>) {
+        let span_queue = BoundedQueue::new(capacity, policy);
+        let (closed_tx, closed_rx) = oneshot::channel();
+
+        let mut spans = span_queue.receiver();
+        let serve = async move {
+            while let Some(span) = spans.next().await {
                 reporter.report(span);
-                Ok(())
-            })
-            .map_err(|_| ());
+            }
+            reporter.shutdown().await;
+            let _ = closed_tx.send(());
+        };
+        let tracer = Self {
+            observer: Arc::new(SamplingObserver::new(sampler.clone(), None)),
+            sampler,
+            span_queue,
+            closed: closed_rx.shared(),
+            text_map_propagator: Arc::new(JaegerPropagator::text_map()),
+            http_headers_propagator: Arc::new(JaegerPropagator::http_headers()),
+        };
 
         (tracer, serve)
     }
 
+    /// Number of finished spans dropped because the span queue was full, or
+    /// because they finished after the tracer was closed.
+    pub fn dropped_spans(&self) -> usize {
+        self.span_queue.dropped()
+    }
+
+    /// Number of finished spans waiting for the reporter.
+    pub fn queued_spans(&self) -> usize {
+        self.span_queue.len()
+    }
+
+    /// Stops accepting spans and shuts the reporter down once it got the
+    /// queued ones, resolving when the transport sent them or failing with
+    /// `Error::Timeout` after `timeout`.
+    ///
+    /// Spans finished afterwards are dropped. The background work must keep
+    /// running until the returned future resolves.
+    pub fn close(&self, timeout: Duration) -> impl Future<Output = crate::Result<()>> {
+        self.sampler.close();
+        self.span_queue.close();
+
+        // A canceled result means the background work is gone, there is
+        // nothing left to wait for.
+        runtime::timeout(timeout, self.closed.clone()).map(|result| match result {
+            Some(_) => Ok(()),
+            None => Err(Error::Timeout),
+        })
+    }
+
+    /// Closes the tracer, blocking the current thread until `close` resolves.
+    ///
+    /// Must not be called from within an async runtime.
+    pub fn close_blocking(&self, timeout: Duration) -> crate::Result<()> {
+        runtime::block_on(self.close(timeout))?
+    }
+
     pub fn builder() -> TracerBuilder {
         TracerBuilder::default()
     }
@@ -79,37 +167,174 @@ impl opentracing_rs_core::Tracer for Tracer {
     where
         N: Into<String>,
     {
-        SpanBuilder::new(operation_name, self.sampler.clone(), self.sender.clone())
+        SpanBuilder::new(
+            operation_name,
+            self.sampler.clone(),
+            self.observer.clone(),
+            self.span_queue.clone(),
+        )
+    }
+
+    fn inject(
+        &self,
+        context: &SpanContext,
+        format: InjectFormat<'_>,
+    ) -> Result<(), PropagationError> {
+        // Once the trace leaves the process its sampling can no longer change.
+        context.state().finalize_sampling();
+
+        match format {
+            Format::TextMap(carrier) => self.text_map_propagator.inject(context, carrier),
+            Format::HttpHeaders(carrier) => self.http_headers_propagator.inject(context, carrier),
+            Format::Binary(carrier) => propagation::inject_binary(context, carrier)?,
+        }
+        Ok(())
+    }
+
+    /// Carriers without a span context but with a `jaeger-debug-id` entry
+    /// yield a context from which spans start a new debug trace.
+    fn extract(&self, format: ExtractFormat<'_>) -> Result<Option<SpanContext>, PropagationError> {
+        let (propagator, carrier) = match format {
+            Format::TextMap(carrier) => (&self.text_map_propagator, carrier),
+            Format::HttpHeaders(carrier) => (&self.http_headers_propagator, carrier),
+            Format::Binary(carrier) => return propagation::extract_binary(carrier),
+        };
+
+        match propagator.extract(carrier)? {
+            Some(context) => Ok(Some(context)),
+            None => Ok(extract_debug_id(carrier).map(|debug_id| {
+                SpanContext::new(SpanState::debug_id_container(debug_id), Vec::new())
+            })),
+        }
     }
 }
 
+fn extract_debug_id(carrier: &dyn TextMapReader) -> Option<String> {
+    carrier
+        .keys()
+        .into_iter()
+        .find(|key| key.eq_ignore_ascii_case(DEBUG_ID_HEADER))
+        .and_then(|key| carrier.get(key))
+        .map(str::trim)
+        .filter(|debug_id| !debug_id.is_empty())
+        .map(str::to_owned)
+}
+
 #[derive(Default)]
 pub struct TracerBuilder {
-    sampler: Option<Arc<Sampler>>,
-    reporter: Option<Box<Reporter>>,
-    reporter_serve: Option<Box<Future<Item = (), Error = ()> + Send>>,
+    sampler: Option<Arc<dyn Sampler>>,
+    sampler_serve: Option<BoxFuture<'static, ()>>,
+    reporter: Option<Box<dyn Reporter>>,
+    reporter_serve: Option<BoxFuture<'static, ()>>,
+    debug_throttler: Option<Arc<dyn DebugThrottler>>,
+    error_handler: Option<Arc<dyn ErrorHandler>>,
+    /// Given to the components configured before `error_handler` is known.
+    forwarding_error_handler: Arc<ForwardingErrorHandler>,
+    text_map_propagator: Option<Arc<dyn TextMapPropagator>>,
+    http_headers_propagator: Option<Arc<dyn TextMapPropagator>>,
+    span_queue_capacity: Option<usize>,
+    span_queue_drop_policy: DropPolicy,
 }
 
 impl TracerBuilder {
+    pub fn sampler<S>(mut self, sampler: S) -> Self
+    where
+        S: Sampler + 'static,
+    {
+        self.sampler = Some(Arc::new(sampler));
+        self
+    }
+
     pub fn const_sampler(mut self, sample: bool) -> Self {
         use crate::ConstSampler;
         self.sampler = Some(Arc::new(ConstSampler::new(sample)));
         self
     }
 
-    pub fn probabilistic_sampler(mut self, sampling_rate: f64) -> Self {
+    pub fn probabilistic_sampler(mut self, sampling_rate: f64) -> crate::Result<Self> {
         use crate::ProbabilisticSampler;
-        self.sampler = Some(Arc::new(ProbabilisticSampler::new(sampling_rate)));
+        self.sampler = Some(Arc::new(ProbabilisticSampler::new(sampling_rate)?));
+        Ok(self)
+    }
+
+    /// Samples at most `max_traces_per_second` traces.
+    pub fn rate_limiting_sampler(mut self, max_traces_per_second: f64) -> crate::Result<Self> {
+        use crate::RateLimitingSampler;
+        self.sampler = Some(Arc::new(RateLimitingSampler::new(max_traces_per_second)?));
+        Ok(self)
+    }
+
+    /// Samples with the strategy served for `service_name` by the agent at
+    /// `sampling_server_url`, usually `http://{agent}:5778/sampling`.
+    ///
+    /// Only available with the `tokio` feature, the strategy is fetched with
+    /// hyper which needs the tokio runtime.
+    #[cfg(feature = "tokio")]
+    pub fn remote_sampler<N>(
+        mut self,
+        service_name: N,
+        sampling_server_url: Uri,
+        refresh_interval: Duration,
+    ) -> crate::Result<Self>
+    where
+        N: Into<String>,
+    {
+        use crate::RemoteSampler;
+        let (sampler, serve) = RemoteSampler::builder(service_name)
+            .sampling_server_url(sampling_server_url)
+            .refresh_interval(refresh_interval)
+            .shared_error_handler(self.shared_error_handler())
+            .build_and_serve()?;
+        self.sampler = Some(Arc::new(sampler));
+        self.sampler_serve = Some(serve.boxed());
+        Ok(self)
+    }
+
+    /// Reports spans through `transport`, flushing it every `flush_interval`.
+    pub fn remote_reporter<T>(mut self, transport: T, flush_interval: Duration) -> Self
+    where
+        T: Transport + Clone + 'static,
+    {
+        use crate::RemoteReporter;
+        let reporter = Box::new(RemoteReporter::new(transport));
+        self.reporter_serve = Some(reporter.interval_flush(flush_interval).boxed());
+        self.reporter = Some(reporter);
+
         self
     }
 
+    /// Reports spans to a Jaeger collector over HTTP, `endpoint` is usually
+    /// `http://{collector}:14268/api/traces`.
+    ///
+    /// Only available with the `tokio` feature, batches are posted with hyper
+    /// which needs the tokio runtime. Use `udp_remote_reporter` with
+    /// async-std.
+    #[cfg(feature = "tokio")]
+    pub fn http_collector_reporter<N>(
+        self,
+        service_name: N,
+        endpoint: Uri,
+        flush_interval: Duration,
+    ) -> crate::Result<Self>
+    where
+        N: Into<String>,
+    {
+        use crate::HttpTransport;
+        let transport = HttpTransport::builder()
+            .process_service_name(service_name)
+            .shared_error_handler(self.shared_error_handler())
+            .build(endpoint)?;
+
+        Ok(self.remote_reporter(transport, flush_interval))
+    }
+
     pub fn udp_remote_reporter<N>(
         mut self,
         service_name: N,
         socket_addr: SocketAddr,
         protocol: TransportProtocol,
         flush_interval: Duration,
-    ) -> Self
+    ) -> crate::Result<Self>
     where
         N: Into<String>,
     {
@@ -117,23 +342,407 @@ impl TracerBuilder {
         let (transport, serve) = UdpTransport::builder()
             .process_service_name(service_name)
             .transport_protocol(protocol)
-            .build_and_serve(socket_addr);
+            .shared_error_handler(self.shared_error_handler())
+            .build_and_serve(socket_addr)?;
         let reporter = Box::new(RemoteReporter::new(transport));
-        self.reporter_serve = Some(Box::new(
-            serve
-                .join(reporter.interval_flush(flush_interval))
-                .map(|_| ()),
-        ));
+        self.reporter_serve = Some(
+            future::join(serve, reporter.interval_flush(flush_interval))
+                .map(|_| ())
+                .boxed(),
+        );
         self.reporter = Some(reporter);
 
+        Ok(self)
+    }
+
+    /// Buffers the spans of each local trace before handing them to the
+    /// reporter configured so far, which only gets the traces matching one of
+    /// `policies`.
+    pub fn tail_sampling_reporter(
+        mut self,
+        policies: Vec<TailSamplingPolicy>,
+        trace_timeout: Duration,
+    ) -> crate::Result<Self> {
+        use crate::TailSamplingReporter;
+        let reporter = self.reporter.take().ok_or_else(|| {
+            Error::InvalidConfig("tail sampling needs a reporter configured first".to_owned())
+        })?;
+        let (reporter, serve) = policies
+            .into_iter()
+            .fold(
+                TailSamplingReporter::builder(reporter),
+                |builder, policy| builder.policy(policy),
+            )
+            .trace_timeout(trace_timeout)
+            .build_and_serve();
+        self.reporter_serve = Some(match self.reporter_serve.take() {
+            Some(reporter_serve) => future::join(reporter_serve, serve).map(|_| ()).boxed(),
+            None => serve.boxed(),
+        });
+        self.reporter = Some(Box::new(reporter));
+
+        Ok(self)
+    }
+
+    /// Handles the failures of the transports and remote sampler, which are
+    /// printed to stderr by default.
+    pub fn error_handler<H>(mut self, error_handler: H) -> Self
+    where
+        H: ErrorHandler + 'static,
+    {
+        self.error_handler = Some(Arc::new(error_handler));
+        self
+    }
+
+    fn shared_error_handler(&self) -> Arc<dyn ErrorHandler> {
+        self.forwarding_error_handler.clone()
+    }
+
+    /// Limits the debug traces started from `jaeger-debug-id` carrier entries
+    /// and `sampling.priority` tags, which are unlimited by default.
+    pub fn debug_throttler<T>(mut self, throttler: T) -> Self
+    where
+        T: DebugThrottler + 'static,
+    {
+        self.debug_throttler = Some(Arc::new(throttler));
         self
     }
 
-    pub fn build_and_serve(self) -> Tracer {
-        let (tracer, tracer_serve) = Tracer::new(self.sampler.unwrap(), self.reporter.unwrap());
-        tokio::spawn(self.reporter_serve.unwrap());
-        tokio::spawn(tracer_serve);
+    /// Sets the propagator used for the `TextMap` format.
+    pub fn text_map_propagator<P>(mut self, propagator: P) -> Self
+    where
+        P: TextMapPropagator + 'static,
+    {
+        self.text_map_propagator = Some(Arc::new(propagator));
+        self
+    }
+
+    /// Sets the propagator used for the `HttpHeaders` format.
+    pub fn http_headers_propagator<P>(mut self, propagator: P) -> Self
+    where
+        P: TextMapPropagator + 'static,
+    {
+        self.http_headers_propagator = Some(Arc::new(propagator));
+        self
+    }
+
+    /// Caps the finished spans waiting for the reporter,
+    /// `DEFAULT_SPAN_QUEUE_CAPACITY` by default.
+    pub fn span_queue_capacity(mut self, capacity: usize) -> Self {
+        self.span_queue_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets which span to drop when the span queue is full, the newest one
+    /// by default.
+    pub fn span_queue_drop_policy(mut self, policy: DropPolicy) -> Self {
+        self.span_queue_drop_policy = policy;
+        self
+    }
+
+    /// Builds the tracer, spawning its background work on the current tokio
+    /// runtime, or on async-std with the `async-std` feature alone.
+    pub fn build_and_serve(self) -> crate::Result<Tracer> {
+        let (tracer, serve) = self.build()?;
+        runtime::spawn(serve);
+
+        Ok(tracer)
+    }
+
+    /// Builds the tracer, running its background work on a dedicated thread.
+    ///
+    /// For applications without an async runtime, such as synchronous
+    /// services and command line tools. Spans can be started and finished
+    /// from any thread.
+    pub fn build_with_background_thread(self) -> crate::Result<Tracer> {
+        let error_handler = self.shared_error_handler();
+        let (tracer, serve) = self.build()?;
+        thread::Builder::new()
+            .name("opentracing-rs-jaeger".to_owned())
+            .spawn(move || {
+                if let Err(err) = runtime::block_on(serve) {
+                    error_handler.handle(err.into());
+                }
+            })?;
+
+        Ok(tracer)
+    }
+
+    fn build(self) -> crate::Result<(Tracer, impl Future<Output = ()> + Send)> {
+        let sampler = self
+            .sampler
+            .ok_or_else(|| Error::InvalidConfig("no sampler configured".to_owned()))?;
+        let reporter = self
+            .reporter
+            .ok_or_else(|| Error::InvalidConfig("no reporter configured".to_owned()))?;
+        let capacity = self
+            .span_queue_capacity
+            .unwrap_or(DEFAULT_SPAN_QUEUE_CAPACITY);
+        if capacity == 0 {
+            return Err(Error::InvalidConfig(
+                "span queue capacity must not be zero".to_owned(),
+            ));
+        }
+        if let Some(error_handler) = self.error_handler {
+            self.forwarding_error_handler.set(error_handler);
+        }
+        let (mut tracer, tracer_serve) =
+            Tracer::with_span_queue(sampler, reporter, capacity, self.span_queue_drop_policy);
+        tracer.observer = Arc::new(SamplingObserver::new(
+            tracer.sampler.clone(),
+            self.debug_throttler,
+        ));
+        if let Some(propagator) = self.text_map_propagator {
+            tracer.text_map_propagator = propagator;
+        }
+        if let Some(propagator) = self.http_headers_propagator {
+            tracer.http_headers_propagator = propagator;
+        }
+
+        let sampler_serve = self.sampler_serve;
+        let reporter_serve = self.reporter_serve;
+        let serve = async move {
+            if let Some(sampler_serve) = sampler_serve {
+                runtime::spawn(sampler_serve);
+            }
+            if let Some(reporter_serve) = reporter_serve {
+                runtime::spawn(reporter_serve);
+            }
+            tracer_serve.await
+        };
+
+        Ok((tracer, serve))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use opentracing_rs_core::{SpanBuilder as _, TagValue, Tracer as _};
+
+    use super::*;
+    use crate::testing::{collecting_error_handler, RecordingReporter};
+    use crate::{ConstSampler, DeferredSampler, RuleSampler, SamplingRule, TraceId};
+
+    fn tracer<S>(sampler: S) -> (Tracer, RecordingReporter)
+    where
+        S: Sampler + 'static,
+    {
+        let reporter = RecordingReporter::default();
+        let (tracer, _serve) = Tracer::new(Arc::new(sampler), Box::new(reporter.clone()));
+        (tracer, reporter)
+    }
+
+    fn remote_parent(state: SpanState) -> SpanContext {
+        SpanContext::new(state, Vec::new())
+    }
+
+    /// Never finishes shutting down.
+    struct StalledReporter;
+
+    impl Reporter for StalledReporter {
+        fn report(&mut self, _span: Span) {}
+
+        fn close(&mut self) {}
+
+        fn shutdown(&mut self) -> BoxFuture<'static, ()> {
+            future::pending().boxed()
+        }
+    }
+
+    #[test]
+    fn rules_only_decide_undecided_parents() {
+        let sampler = RuleSampler::new(ConstSampler::new(false))
+            .rule(SamplingRule::new(ConstSampler::new(true)).operation("child"));
+        let (mut tracer, _) = tracer(sampler);
+        let mut is_sampled = |operation: &str, parent: &SpanContext| {
+            let span = tracer.span(operation).child_of(parent).start();
+            let state = span.context().state();
+            (state.is_sampled(), state.is_sampling_final())
+        };
+
+        let sampled = remote_parent(SpanState::new(TraceId::new(), 1, true));
+        assert_eq!(is_sampled("other", &sampled), (true, true));
+
+        let unsampled = remote_parent(SpanState::new(TraceId::new(), 1, false));
+        assert_eq!(is_sampled("child", &unsampled), (false, true));
+
+        let deferred = remote_parent(SpanState::undecided(TraceId::new(), 1));
+        assert_eq!(is_sampled("child", &deferred), (true, true));
+        let deferred = remote_parent(SpanState::undecided(TraceId::new(), 1));
+        assert_eq!(is_sampled("other", &deferred), (false, true));
+    }
+
+    #[test]
+    fn injects_and_extracts_every_format() {
+        let (mut tracer, _) = tracer(ConstSampler::new(true));
+        let mut span = tracer.span("op").start();
+        span.set_baggage_item("user", "alice");
+        let context = span.context();
+
+        let mut buf = Vec::new();
+        tracer.inject(context, Format::Binary(&mut buf)).unwrap();
+        let mut text_map = HashMap::new();
+        tracer
+            .inject(context, Format::TextMap(&mut text_map))
+            .unwrap();
+        let mut headers = HashMap::new();
+        tracer
+            .inject(context, Format::HttpHeaders(&mut headers))
+            .unwrap();
+        assert!(text_map.contains_key("uber-trace-id"));
+        assert!(headers.contains_key("uber-trace-id"));
+
+        let extracted = [
+            tracer.extract(Format::Binary(&mut &buf[..])),
+            tracer.extract(Format::TextMap(&text_map)),
+            tracer.extract(Format::HttpHeaders(&headers)),
+        ];
+        for extracted in extracted {
+            let extracted = extracted.unwrap().unwrap();
+            assert_eq!(extracted.state().trace_id(), context.state().trace_id());
+            assert_eq!(extracted.state().span_id(), context.state().span_id());
+            assert!(extracted.state().is_sampled());
+            assert_eq!(extracted.baggage_item("user"), Some("alice"));
+        }
+    }
+
+    #[test]
+    fn injecting_finalizes_sampling() {
+        let (mut tracer, _) = tracer(DeferredSampler::new(ConstSampler::new(false)));
+        let span = tracer.span("op").start();
+        assert!(!span.context().state().is_sampling_final());
 
         tracer
+            .inject(span.context(), Format::TextMap(&mut HashMap::new()))
+            .unwrap();
+        assert!(span.context().state().is_sampling_final());
+    }
+
+    #[test]
+    fn rejects_corrupt_binary_carriers() {
+        let (tracer, _) = tracer(ConstSampler::new(true));
+
+        let result = tracer.extract(Format::Binary(&mut &[0u8; 12][..]));
+        assert!(matches!(
+            result,
+            Err(PropagationError::SpanContextCorrupted(_))
+        ));
+        assert!(tracer
+            .extract(Format::Binary(&mut &[][..]))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn debug_id_starts_debug_trace() {
+        let (mut tracer, _) = tracer(ConstSampler::new(false));
+        let mut carrier = HashMap::new();
+        carrier.insert("Jaeger-Debug-Id".to_owned(), " support-1234 ".to_owned());
+
+        let context = tracer
+            .extract(Format::HttpHeaders(&carrier))
+            .unwrap()
+            .unwrap();
+        let span = tracer.span("op").child_of(&context).start();
+
+        let state = span.context().state();
+        assert!(state.is_sampled());
+        assert!(state.is_debug());
+        assert!(state.is_sampling_final());
+        assert_eq!(state.parent_span_id(), None);
+        assert!(span.references().is_empty());
+        let debug_id = span
+            .tags()
+            .iter()
+            .find(|tag| tag.name() == crate::tag::JAEGER_DEBUG_ID_TAG_KEY)
+            .map(Tag::value);
+        assert_eq!(debug_id, Some(&TagValue::String("support-1234".to_owned())));
+    }
+
+    #[test]
+    fn ignores_empty_debug_ids() {
+        let (tracer, _) = tracer(ConstSampler::new(false));
+        let mut carrier = HashMap::new();
+        carrier.insert("jaeger-debug-id".to_owned(), " ".to_owned());
+
+        assert!(tracer.extract(Format::TextMap(&carrier)).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn close_drains_queued_spans() {
+        let reporter = RecordingReporter::default();
+        let (mut tracer, serve) = Tracer::new(
+            Arc::new(ConstSampler::new(true)),
+            Box::new(reporter.clone()),
+        );
+        for operation in ["first", "second", "third"] {
+            drop(tracer.span(operation).start());
+        }
+        assert_eq!(tracer.queued_spans(), 3);
+
+        tokio::spawn(serve);
+        tracer.close(Duration::from_secs(5)).await.unwrap();
+        assert_eq!(reporter.operations(), ["first", "second", "third"]);
+        assert!(reporter.is_closed());
+
+        drop(tracer.span("late").start());
+        assert_eq!(tracer.dropped_spans(), 1);
+    }
+
+    #[tokio::test]
+    async fn close_times_out_when_shutdown_stalls() {
+        let (tracer, serve) =
+            Tracer::new(Arc::new(ConstSampler::new(true)), Box::new(StalledReporter));
+        tokio::spawn(serve);
+
+        let result = tracer.close(Duration::from_millis(20)).await;
+        assert!(matches!(result, Err(Error::Timeout)));
+    }
+
+    #[test]
+    fn close_blocking_waits_for_reporter() {
+        let reporter = RecordingReporter::default();
+        let (mut tracer, serve) = Tracer::new(
+            Arc::new(ConstSampler::new(true)),
+            Box::new(reporter.clone()),
+        );
+        drop(tracer.span("op").start());
+
+        let background = thread::spawn(move || runtime::block_on(serve).unwrap());
+        tracer.close_blocking(Duration::from_secs(5)).unwrap();
+        background.join().unwrap();
+        assert_eq!(reporter.operations(), ["op"]);
+        assert!(reporter.is_closed());
+    }
+
+    #[test]
+    fn background_thread_delivers_spans() {
+        let reporter = RecordingReporter::default();
+        let mut builder = Tracer::builder().const_sampler(true);
+        builder.reporter = Some(Box::new(reporter.clone()));
+        let tracer = builder.build_with_background_thread().unwrap();
+
+        let mut worker = tracer.clone();
+        thread::spawn(move || drop(worker.span("op").start()))
+            .join()
+            .unwrap();
+        tracer.close_blocking(Duration::from_secs(5)).unwrap();
+        assert_eq!(reporter.operations(), ["op"]);
+        assert!(reporter.is_closed());
+    }
+
+    #[test]
+    fn error_handler_applies_to_components_configured_before_it() {
+        let (error_handler, errors) = collecting_error_handler();
+        let mut builder = Tracer::builder().const_sampler(true);
+        builder.reporter = Some(Box::new(RecordingReporter::default()));
+        // As handed to a transport configured before the handler.
+        let transport_error_handler = builder.shared_error_handler();
+
+        let (_tracer, _serve) = builder.error_handler(error_handler).build().unwrap();
+        transport_error_handler.handle(Error::Timeout);
+        assert!(matches!(errors.lock().unwrap()[..], [Error::Timeout]));
     }
 }
@@ -0,0 +1,586 @@
+use std::error;
+use std::fmt;
+use std::pin::Pin;
+use std::result;
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+use futures::future::{self, BoxFuture, FutureExt};
+use futures::Future;
+use hyper::{
+    client::HttpConnector,
+    header::{HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    Body, Client, Request, StatusCode, Uri,
+};
+use thrift::protocol::{TBinaryOutputProtocol, TOutputProtocol};
+
+use opentracing_rs_core::Tag;
+
+use super::{build_process, SpanBatch, Transport};
+use crate::{
+    runtime, thrift_gen::jaeger, Error, ErrorHandler, LoggingErrorHandler, Process, Result, Span,
+};
+
+const THRIFT_CONTENT_TYPE: &str = "application/vnd.apache.thrift.binary";
+
+#[derive(Debug)]
+pub enum HttpTransportError {
+    Encode(thrift::Error),
+    Request(hyper::Error),
+    Timeout,
+    /// The collector answered with a non-success status code.
+    Status(StatusCode),
+}
+
+impl fmt::Display for HttpTransportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HttpTransportError::Encode(err) => write!(f, "failed to encode batch: {}", err),
+            HttpTransportError::Request(err) => write!(f, "failed to send batch: {}", err),
+            HttpTransportError::Timeout => write!(f, "timed out sending batch"),
+            HttpTransportError::Status(status) => {
+                write!(f, "collector rejected batch with status {}", status)
+            }
+        }
+    }
+}
+
+impl error::Error for HttpTransportError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            HttpTransportError::Encode(err) => Some(err),
+            HttpTransportError::Request(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Sends span batches to the `/api/traces` endpoint of a Jaeger collector,
+/// encoded as a Thrift binary `jaeger.Batch`.
+///
+/// Requires the `tokio` feature.
+#[derive(Clone)]
+pub struct HttpTransport {
+    process: Arc<Process>,
+    span_buffer: Arc<RwLock<Vec<Span>>>,
+    buffer_size: usize,
+    client: Client<HttpConnector>,
+    endpoint: Uri,
+    authorization: Option<HeaderValue>,
+    timeout: Duration,
+    error_handler: Arc<dyn ErrorHandler>,
+    /// Batches sent by `flush` that are still waiting for the collector.
+    in_flight: Arc<InFlight>,
+}
+
+impl HttpTransport {
+    pub fn builder() -> HttpTransportBuilder {
+        HttpTransportBuilder::default()
+    }
+
+    /// Posts a batch to the collector, resolving once it has been accepted.
+    pub fn send_batch(
+        &self,
+        batch: SpanBatch,
+    ) -> BoxFuture<'static, result::Result<(), HttpTransportError>> {
+        let body = match encode_batch(batch) {
+            Ok(body) => body,
+            Err(err) => return future::err(HttpTransportError::Encode(err)).boxed(),
+        };
+
+        let mut request =
+            Request::post(self.endpoint.clone()).header(CONTENT_TYPE, THRIFT_CONTENT_TYPE);
+        if let Some(authorization) = &self.authorization {
+            request = request.header(AUTHORIZATION, authorization.clone());
+        }
+        let request = request
+            .body(Body::from(body))
+            .expect("request parts are validated by the builder");
+
+        let response = runtime::timeout(self.timeout, self.client.request(request));
+
+        async move {
+            let response = response
+                .await
+                .ok_or(HttpTransportError::Timeout)?
+                .map_err(HttpTransportError::Request)?;
+
+            if response.status().is_success() {
+                Ok(())
+            } else {
+                Err(HttpTransportError::Status(response.status()))
+            }
+        }
+        .boxed()
+    }
+
+    /// Sends the buffered spans as one batch, reporting failures to the error
+    /// handler, or returns `None` if nothing is buffered.
+    fn send_buffered(&self) -> Option<impl Future<Output = ()>> {
+        let batch = {
+            let mut buf = self.span_buffer.write().unwrap();
+
+            if buf.is_empty() {
+                return None;
+            }
+
+            SpanBatch {
+                process: self.process.as_ref().clone(),
+                spans: buf.drain(..).collect(),
+            }
+        };
+
+        let error_handler = self.error_handler.clone();
+        let send = self.send_batch(batch);
+        Some(async move {
+            if let Err(err) = send.await {
+                error_handler.handle(err.into());
+            }
+        })
+    }
+}
+
+impl Transport for HttpTransport {
+    fn append(&mut self, span: Span) {
+        if !span.context().state().is_sampled() {
+            return;
+        }
+
+        {
+            let mut buf = self.span_buffer.write().unwrap();
+            buf.push(span);
+            if buf.len() < self.buffer_size {
+                return;
+            }
+        }
+
+        self.flush();
+    }
+
+    fn flush(&mut self) {
+        if let Some(send) = self.send_buffered() {
+            runtime::spawn(self.in_flight.track(send));
+        }
+    }
+
+    /// Sends the buffered spans, resolving once the collector answered them
+    /// and every batch sent by an earlier flush. Each request gives up after
+    /// the configured timeout.
+    fn shutdown(&mut self) -> BoxFuture<'static, ()> {
+        let send = self.send_buffered();
+        let idle = self.in_flight.idle();
+
+        async move {
+            if let Some(send) = send {
+                send.await;
+            }
+            idle.await;
+        }
+        .boxed()
+    }
+}
+
+/// Counts the batches being sent in the background.
+#[derive(Default)]
+struct InFlight {
+    state: Mutex<InFlightState>,
+}
+
+#[derive(Default)]
+struct InFlightState {
+    count: usize,
+    waiters: Vec<Waker>,
+}
+
+impl InFlight {
+    /// Counts `future` as in flight until it completes or is dropped.
+    fn track<F>(self: &Arc<Self>, future: F) -> impl Future<Output = ()>
+    where
+        F: Future<Output = ()>,
+    {
+        self.state.lock().unwrap().count += 1;
+        let guard = InFlightGuard(self.clone());
+
+        async move {
+            future.await;
+            drop(guard);
+        }
+    }
+
+    /// Resolves once nothing is in flight.
+    fn idle(self: &Arc<Self>) -> Idle {
+        Idle(self.clone())
+    }
+}
+
+struct InFlightGuard(Arc<InFlight>);
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap();
+        state.count -= 1;
+        if state.count == 0 {
+            for waiter in state.waiters.drain(..) {
+                waiter.wake();
+            }
+        }
+    }
+}
+
+struct Idle(Arc<InFlight>);
+
+impl Future for Idle {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.0.state.lock().unwrap();
+        if state.count == 0 {
+            Poll::Ready(())
+        } else {
+            state.waiters.push(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+fn encode_batch(batch: SpanBatch) -> thrift::Result<Vec<u8>> {
+    let batch: jaeger::Batch = From::from(batch);
+    let mut buf = Vec::new();
+
+    {
+        let mut protocol = TBinaryOutputProtocol::new(&mut buf, true);
+        batch.write_to_out_protocol(&mut protocol)?;
+        protocol.flush()?;
+    }
+
+    Ok(buf)
+}
+
+pub struct HttpTransportBuilder {
+    service_name: Option<String>,
+    tags: Option<Vec<Tag>>,
+    span_buffer_size: usize,
+    authorization: Option<String>,
+    timeout: Duration,
+    error_handler: Option<Arc<dyn ErrorHandler>>,
+}
+
+impl Default for HttpTransportBuilder {
+    fn default() -> Self {
+        Self {
+            service_name: None,
+            tags: None,
+            span_buffer_size: 1000,
+            authorization: None,
+            timeout: Duration::from_secs(5),
+            error_handler: None,
+        }
+    }
+}
+
+impl HttpTransportBuilder {
+    pub fn process_service_name<N>(mut self, service_name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        self.service_name = Some(service_name.into());
+        self
+    }
+
+    pub fn process_tags(mut self, tags: Vec<Tag>) -> Self {
+        self.tags = Some(tags);
+        self
+    }
+
+    pub fn span_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.span_buffer_size = buffer_size;
+        self
+    }
+
+    /// Authenticates with HTTP basic auth.
+    pub fn basic_auth(mut self, username: &str, password: &str) -> Self {
+        let credentials = base64::encode(&format!("{}:{}", username, password));
+        self.authorization = Some(format!("Basic {}", credentials));
+        self
+    }
+
+    /// Authenticates with a bearer token.
+    pub fn bearer_token(mut self, token: &str) -> Self {
+        self.authorization = Some(format!("Bearer {}", token));
+        self
+    }
+
+    /// Sets how long to wait for the collector to answer a request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the handler of batches failing to encode or send, which are
+    /// printed to stderr by default.
+    pub fn error_handler<H>(mut self, error_handler: H) -> Self
+    where
+        H: ErrorHandler + 'static,
+    {
+        self.error_handler = Some(Arc::new(error_handler));
+        self
+    }
+
+    pub(crate) fn shared_error_handler(mut self, error_handler: Arc<dyn ErrorHandler>) -> Self {
+        self.error_handler = Some(error_handler);
+        self
+    }
+
+    /// Builds a transport posting to `endpoint`, usually
+    /// `http://{collector}:14268/api/traces`.
+    pub fn build(self, endpoint: Uri) -> Result<HttpTransport> {
+        if self.span_buffer_size == 0 {
+            return Err(Error::InvalidConfig(
+                "span buffer size must not be zero".to_owned(),
+            ));
+        }
+        let authorization = match self.authorization {
+            Some(authorization) => Some(
+                HeaderValue::from_str(&authorization)
+                    .map_err(|_| Error::InvalidConfig("invalid authorization header".to_owned()))?,
+            ),
+            None => None,
+        };
+
+        Ok(HttpTransport {
+            process: Arc::new(build_process(self.service_name, self.tags)),
+            span_buffer: Arc::new(RwLock::new(Vec::with_capacity(self.span_buffer_size))),
+            buffer_size: self.span_buffer_size,
+            client: Client::new(),
+            endpoint,
+            authorization,
+            timeout: self.timeout,
+            error_handler: self
+                .error_handler
+                .unwrap_or_else(|| Arc::new(LoggingErrorHandler::default())),
+            in_flight: Arc::new(InFlight::default()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::time::Instant;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::task::JoinHandle;
+
+    use super::*;
+    use crate::{testing, SpanState, TraceId};
+
+    struct ReceivedRequest {
+        request_line: String,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    async fn read_request(stream: &mut TcpStream) -> ReceivedRequest {
+        let mut buf = Vec::new();
+        let head_len = loop {
+            if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+            let mut chunk = [0; 1024];
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "connection closed before the request head");
+            buf.extend_from_slice(&chunk[..n]);
+        };
+
+        let head = String::from_utf8(buf[..head_len].to_vec()).unwrap();
+        let mut lines = head.trim_end().split("\r\n");
+        let request_line = lines.next().unwrap().to_owned();
+        let headers: HashMap<String, String> = lines
+            .map(|line| {
+                let (name, value) = line.split_at(line.find(':').unwrap());
+                (name.to_ascii_lowercase(), value[1..].trim().to_owned())
+            })
+            .collect();
+
+        let content_length: usize = headers["content-length"].parse().unwrap();
+        let mut body = buf[head_len..].to_vec();
+        while body.len() < content_length {
+            let mut chunk = [0; 1024];
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "connection closed before the request body");
+            body.extend_from_slice(&chunk[..n]);
+        }
+
+        ReceivedRequest {
+            request_line,
+            headers,
+            body,
+        }
+    }
+
+    /// Answers a single request with `status`.
+    async fn serve_once(status: &'static str) -> (Uri, JoinHandle<ReceivedRequest>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/api/traces", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let request = read_request(&mut stream).await;
+            let response = format!(
+                "HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                status
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+            request
+        });
+
+        (endpoint, server)
+    }
+
+    fn sampled_span() -> Span {
+        let state = SpanState::new(TraceId::new(), rand::random(), true);
+        testing::finished_span("op", state, Vec::new())
+    }
+
+    fn batch() -> SpanBatch {
+        SpanBatch {
+            process: Process::new("http-test"),
+            spans: vec![sampled_span()],
+        }
+    }
+
+    #[tokio::test]
+    async fn posts_thrift_batches() {
+        let (endpoint, server) = serve_once("202 Accepted").await;
+        let transport = HttpTransport::builder().build(endpoint).unwrap();
+
+        transport.send_batch(batch()).await.unwrap();
+
+        let request = server.await.unwrap();
+        assert_eq!(request.request_line, "POST /api/traces HTTP/1.1");
+        assert_eq!(request.headers["content-type"], THRIFT_CONTENT_TYPE);
+        assert!(!request.headers.contains_key("authorization"));
+        assert!(request
+            .body
+            .windows("http-test".len())
+            .any(|w| w == b"http-test"));
+    }
+
+    #[tokio::test]
+    async fn sends_basic_auth() {
+        let (endpoint, server) = serve_once("200 OK").await;
+        let transport = HttpTransport::builder()
+            .basic_auth("user", "secret")
+            .build(endpoint)
+            .unwrap();
+
+        transport.send_batch(batch()).await.unwrap();
+
+        let request = server.await.unwrap();
+        assert_eq!(request.headers["authorization"], "Basic dXNlcjpzZWNyZXQ=");
+    }
+
+    #[tokio::test]
+    async fn sends_bearer_token() {
+        let (endpoint, server) = serve_once("200 OK").await;
+        let transport = HttpTransport::builder()
+            .bearer_token("t0k3n")
+            .build(endpoint)
+            .unwrap();
+
+        transport.send_batch(batch()).await.unwrap();
+
+        let request = server.await.unwrap();
+        assert_eq!(request.headers["authorization"], "Bearer t0k3n");
+    }
+
+    #[test]
+    fn rejects_invalid_authorization() {
+        let result = HttpTransport::builder()
+            .bearer_token("line\nbreak")
+            .build(Uri::from_static("http://localhost/api/traces"));
+        assert!(matches!(result, Err(Error::InvalidConfig(_))));
+    }
+
+    #[tokio::test]
+    async fn reports_rejected_batches_to_error_handler() {
+        let (endpoint, server) = serve_once("503 Service Unavailable").await;
+        let (error_handler, errors) = testing::collecting_error_handler();
+        let mut transport = HttpTransport::builder()
+            .error_handler(error_handler)
+            .build(endpoint)
+            .unwrap();
+
+        transport.append(sampled_span());
+        transport.shutdown().await;
+        server.await.unwrap();
+
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        match &errors[0] {
+            Error::Http(HttpTransportError::Status(status)) => {
+                assert_eq!(*status, StatusCode::SERVICE_UNAVAILABLE)
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn shutdown_waits_for_flushed_batches() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/api/traces", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        // Answers the flushed batch only after a while.
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            read_request(&mut stream).await;
+            runtime::sleep(Duration::from_millis(200)).await;
+            let response =
+                "HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n";
+            stream.write_all(response.as_bytes()).await.unwrap();
+        });
+
+        let (error_handler, errors) = testing::collecting_error_handler();
+        let mut transport = HttpTransport::builder()
+            .error_handler(error_handler)
+            .build(endpoint)
+            .unwrap();
+
+        transport.append(sampled_span());
+        transport.flush();
+        transport.shutdown().await;
+
+        assert_eq!(errors.lock().unwrap().len(), 1);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn times_out_unanswered_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/api/traces", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        // Reads the request but never answers it.
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            read_request(&mut stream).await;
+            stream
+        });
+
+        let transport = HttpTransport::builder()
+            .timeout(Duration::from_millis(100))
+            .build(endpoint)
+            .unwrap();
+
+        let started = Instant::now();
+        let result = transport.send_batch(batch()).await;
+        assert!(matches!(result, Err(HttpTransportError::Timeout)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        drop(server.await.unwrap());
+    }
+}
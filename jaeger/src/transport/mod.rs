@@ -0,0 +1,164 @@
+use bytes::Bytes;
+use futures::future::{self, BoxFuture, FutureExt};
+use thrift::{
+    protocol::{
+        TBinaryOutputProtocol, TCompactOutputProtocol, TFieldIdentifier, TMessageIdentifier,
+        TMessageType, TOutputProtocol, TStructIdentifier, TType,
+    },
+    transport::{ReadHalf, TBufferChannel, TIoChannel},
+    TransportError, TransportErrorKind,
+};
+
+use crate::{thrift_gen::jaeger, Process, Span};
+use opentracing_rs_core::Tag;
+
+#[cfg(feature = "tokio")]
+mod http;
+mod udp;
+
+#[cfg(feature = "tokio")]
+pub use self::http::{HttpTransport, HttpTransportBuilder, HttpTransportError};
+pub use self::udp::{UdpTransport, UdpTransportBuilder};
+
+pub trait Transport: Send {
+    fn append(&mut self, span: Span);
+    fn flush(&mut self);
+
+    /// Flushes the transport for the last time, the returned future resolves
+    /// once everything appended has been sent.
+    fn shutdown(&mut self) -> BoxFuture<'static, ()> {
+        self.flush();
+        future::ready(()).boxed()
+    }
+}
+
+pub struct SpanBatch {
+    pub(crate) process: Process,
+    pub(crate) spans: Vec<Span>,
+}
+
+pub enum TransportProtocol {
+    ThriftBinary,
+    ThriftCompact,
+}
+
+pub struct ThriftEncoder {
+    protocol: Box<dyn TOutputProtocol + Send + Sync>,
+    buffer: ReadHalf<TBufferChannel>,
+    buffer_size: usize,
+    seq_number: i32,
+}
+
+impl ThriftEncoder {
+    pub fn binary_protocol(buffer_size: usize) -> Self {
+        let thrift_channel = TBufferChannel::with_capacity(0, buffer_size);
+        let (read_buf, write_buf) = thrift_channel.split().unwrap();
+
+        Self {
+            protocol: Box::new(TBinaryOutputProtocol::new(write_buf, true)),
+            buffer: read_buf,
+            buffer_size,
+            seq_number: 0,
+        }
+    }
+
+    pub fn compact_protocol(buffer_size: usize) -> Self {
+        let thrift_channel = TBufferChannel::with_capacity(0, buffer_size);
+        let (read_buf, write_buf) = thrift_channel.split().unwrap();
+
+        Self {
+            protocol: Box::new(TCompactOutputProtocol::new(write_buf)),
+            buffer: read_buf,
+            buffer_size,
+            seq_number: 0,
+        }
+    }
+
+    /// Encodes `batch` as an agent `emitBatch` message.
+    ///
+    /// Fails with a `SizeLimit` transport error when the message does not fit
+    /// in the encoder's buffer.
+    pub(crate) fn encode_batch(&mut self, batch: &jaeger::Batch) -> thrift::Result<Bytes> {
+        let seq = {
+            self.seq_number += 1;
+            self.seq_number
+        };
+
+        let result = {
+            let message_ident = TMessageIdentifier::new("emitBatch", TMessageType::OneWay, seq);
+            let protocol = &mut self.protocol;
+
+            // Same layout as `agent::EmitBatchArgs`, without taking ownership
+            // of the batch.
+            protocol
+                .write_message_begin(&message_ident)
+                .and_then(|_| {
+                    protocol.write_struct_begin(&TStructIdentifier::new("emitBatch_args"))
+                })
+                .and_then(|_| {
+                    protocol.write_field_begin(&TFieldIdentifier::new("batch", TType::Struct, 1))
+                })
+                .and_then(|_| batch.write_to_out_protocol(protocol))
+                .and_then(|_| protocol.write_field_end())
+                .and_then(|_| protocol.write_field_stop())
+                .and_then(|_| protocol.write_struct_end())
+                .and_then(|_| protocol.write_message_end())
+                .and_then(|_| protocol.flush())
+        };
+
+        let buf = self.buffer.write_bytes();
+        self.buffer.empty_write_buffer();
+        self.check_overflow(result, buf.len())?;
+
+        Ok(Bytes::from(buf))
+    }
+
+    /// Returns the number of bytes `write` emits with this encoder's protocol.
+    pub(crate) fn encoded_size<F>(&mut self, write: F) -> thrift::Result<usize>
+    where
+        F: FnOnce(&mut dyn TOutputProtocol) -> thrift::Result<()>,
+    {
+        let result = write(&mut *self.protocol).and_then(|_| self.protocol.flush());
+
+        let size = self.buffer.write_bytes().len();
+        self.buffer.empty_write_buffer();
+        self.check_overflow(result, size)?;
+
+        Ok(size)
+    }
+
+    /// Reports a write that failed on a full buffer as a size limit error.
+    fn check_overflow(&self, result: thrift::Result<()>, written: usize) -> thrift::Result<()> {
+        match result {
+            Err(_) if written >= self.buffer_size => Err(thrift::Error::Transport(
+                TransportError::new(TransportErrorKind::SizeLimit, "encoder buffer is full"),
+            )),
+            result => result,
+        }
+    }
+}
+
+/// Whether encoding failed because the message exceeds the encoder's buffer.
+pub(crate) fn is_size_limit(err: &thrift::Error) -> bool {
+    matches!(
+        err,
+        thrift::Error::Transport(TransportError {
+            kind: TransportErrorKind::SizeLimit,
+            ..
+        })
+    )
+}
+
+/// Builds the process reported with every batch, tagged with the client
+/// version.
+fn build_process(service_name: Option<String>, tags: Option<Vec<Tag>>) -> Process {
+    let service_name = service_name.unwrap_or_else(|| "opentracing-rs_service".to_owned());
+
+    let mut tags = tags.unwrap_or_default();
+    tags.push(Tag::new(
+        crate::tag::JAEGER_CLIENT_VERSION_TAG_KEY,
+        crate::tag::JAEGER_CLIENT_VERSION,
+    ));
+
+    Process::with_tags(service_name, tags)
+}
@@ -0,0 +1,436 @@
+use std::net::{self, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+use bytes::Bytes;
+use futures::channel::oneshot;
+use futures::future::{BoxFuture, FutureExt, Shared};
+use futures::{Future, StreamExt};
+
+use opentracing_rs_core::Tag;
+
+use super::{build_process, is_size_limit, ThriftEncoder, Transport, TransportProtocol};
+use crate::{
+    queue::{BoundedQueue, QueueReceiver},
+    runtime,
+    thrift_gen::jaeger,
+    DropPolicy, Error, ErrorHandler, LoggingErrorHandler, Process, Span,
+};
+
+/// Maximum size of a UDP datagram accepted by the Jaeger agent.
+pub const DEFAULT_MAX_PACKET_SIZE: usize = 65000;
+
+/// Number of encoded packets waiting to be sent by default.
+pub const DEFAULT_MAX_QUEUED_PACKETS: usize = 1000;
+
+/// Upper bound of the bytes an `emitBatch` message adds around the process
+/// and spans.
+const EMIT_BATCH_OVERHEAD: usize = 70;
+
+#[derive(Clone)]
+pub struct UdpTransport {
+    process: Arc<jaeger::Process>,
+    to_send: Arc<BoundedQueue<Bytes>>,
+    span_buffer: Arc<RwLock<SpanBuffer>>,
+    encoder: Arc<RwLock<ThriftEncoder>>,
+    buffer_size: usize,
+    max_packet_size: usize,
+    /// Bytes left for spans in a single packet.
+    max_span_bytes: usize,
+    too_large_spans: Arc<AtomicUsize>,
+    error_handler: Arc<dyn ErrorHandler>,
+    /// Resolves once the stream sent every packet of a closed queue.
+    drained: Shared<oneshot::Receiver<()>>,
+}
+
+#[derive(Default)]
+struct SpanBuffer {
+    spans: Vec<jaeger::Span>,
+    byte_size: usize,
+}
+
+impl UdpTransport {
+    pub(crate) fn new(
+        process: Process,
+        to_send: Arc<BoundedQueue<Bytes>>,
+        mut encoder: ThriftEncoder,
+        buffer_size: usize,
+        max_packet_size: usize,
+        error_handler: Arc<dyn ErrorHandler>,
+        drained: oneshot::Receiver<()>,
+    ) -> Self {
+        let process: jaeger::Process = From::from(process);
+        let process_size = encoder
+            .encoded_size(|protocol| process.write_to_out_protocol(protocol))
+            .unwrap_or(max_packet_size);
+
+        Self {
+            process: Arc::new(process),
+            encoder: Arc::new(RwLock::new(encoder)),
+            to_send,
+            span_buffer: Arc::new(RwLock::new(SpanBuffer::default())),
+            buffer_size,
+            max_packet_size,
+            max_span_bytes: max_packet_size.saturating_sub(EMIT_BATCH_OVERHEAD + process_size),
+            too_large_spans: Arc::new(AtomicUsize::new(0)),
+            error_handler,
+            drained: drained.shared(),
+        }
+    }
+
+    pub fn builder() -> UdpTransportBuilder {
+        UdpTransportBuilder::default()
+    }
+
+    /// Queues `data` to be sent as a single datagram.
+    pub fn send_bytes(&mut self, data: &[u8]) {
+        self.to_send.push(Bytes::from(data));
+    }
+
+    /// Number of packets dropped because too many were waiting to be sent.
+    pub fn dropped_packets(&self) -> usize {
+        self.to_send.dropped()
+    }
+
+    /// Number of spans dropped because they do not fit in a single packet.
+    pub fn too_large_spans(&self) -> usize {
+        self.too_large_spans.load(Ordering::Relaxed)
+    }
+
+    /// Encodes `spans` into as many packets as needed, splitting the batch
+    /// whenever its encoding exceeds the maximum packet size.
+    fn send_spans(&self, spans: Vec<jaeger::Span>) {
+        if spans.is_empty() {
+            return;
+        }
+
+        let batch = jaeger::Batch::new(self.process.as_ref().clone(), spans);
+        let encoded = self.encoder.write().unwrap().encode_batch(&batch);
+        let mut spans = batch.spans;
+
+        match encoded {
+            Ok(buf) if buf.len() <= self.max_packet_size => {
+                self.to_send.push(buf);
+            }
+            _ if spans.len() > 1 => {
+                let rest = spans.split_off(spans.len() / 2);
+                self.send_spans(spans);
+                self.send_spans(rest);
+            }
+            Ok(_) => {
+                self.too_large_spans.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(ref err) if is_size_limit(err) => {
+                self.too_large_spans.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(err) => self.error_handler.handle(Error::Encode(err)),
+        }
+    }
+
+    fn take_spans(&self) -> Vec<jaeger::Span> {
+        let mut buf = self.span_buffer.write().unwrap();
+        buf.byte_size = 0;
+        buf.spans.drain(..).collect()
+    }
+}
+
+impl Transport for UdpTransport {
+    fn append(&mut self, span: Span) {
+        if !span.context().state().is_sampled() {
+            return;
+        }
+
+        let span: jaeger::Span = From::from(span);
+        let span_size = self
+            .encoder
+            .write()
+            .unwrap()
+            .encoded_size(|protocol| span.write_to_out_protocol(protocol));
+
+        let span_size = match span_size {
+            Ok(size) if size <= self.max_span_bytes => size,
+            Ok(_) => {
+                self.too_large_spans.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            Err(ref err) if is_size_limit(err) => {
+                self.too_large_spans.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+            Err(err) => {
+                self.error_handler.handle(Error::Encode(err));
+                return;
+            }
+        };
+
+        let (overflow, full) = {
+            let mut buf = self.span_buffer.write().unwrap();
+
+            // Send what is buffered first if this span would not fit with it.
+            let overflow = if buf.byte_size + span_size > self.max_span_bytes {
+                buf.byte_size = 0;
+                buf.spans.drain(..).collect()
+            } else {
+                Vec::new()
+            };
+
+            buf.spans.push(span);
+            buf.byte_size += span_size;
+
+            (overflow, buf.spans.len() >= self.buffer_size)
+        };
+
+        self.send_spans(overflow);
+        if full {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        let spans = self.take_spans();
+        self.send_spans(spans);
+    }
+
+    /// Flushes and closes the packet queue, resolving once every queued
+    /// packet has been written to the socket.
+    fn shutdown(&mut self) -> BoxFuture<'static, ()> {
+        self.flush();
+        self.to_send.close();
+        // A dropped sender means the sending future is gone, nothing is left
+        // to wait for.
+        self.drained.clone().map(|_| ()).boxed()
+    }
+}
+
+/// Sends every encoded `emitBatch` message as its own datagram.
+struct UdpSender {
+    socket: net::UdpSocket,
+    agent_addr: SocketAddr,
+    to_send: QueueReceiver<Bytes>,
+    drained: oneshot::Sender<()>,
+    error_handler: Arc<dyn ErrorHandler>,
+}
+
+impl UdpSender {
+    fn new(
+        agent_addr: SocketAddr,
+        to_send: QueueReceiver<Bytes>,
+        drained: oneshot::Sender<()>,
+        error_handler: Arc<dyn ErrorHandler>,
+    ) -> crate::Result<Self> {
+        let local_addr = if agent_addr.is_ipv4() {
+            SocketAddr::from(([0, 0, 0, 0], 0))
+        } else {
+            SocketAddr::from(([0u16; 8], 0))
+        };
+        let socket = net::UdpSocket::bind(local_addr)?;
+
+        Ok(Self {
+            socket,
+            agent_addr,
+            to_send,
+            drained,
+            error_handler,
+        })
+    }
+
+    /// Sends packets until the queue is closed and empty, dropping the ones
+    /// that fail to send.
+    async fn run(mut self) {
+        let socket = match runtime::UdpSocket::from_std(self.socket) {
+            Ok(socket) => socket,
+            Err(err) => return self.error_handler.handle(Error::Io(err)),
+        };
+
+        while let Some(packet) = self.to_send.next().await {
+            if let Err(err) = socket.send_to(&packet, self.agent_addr).await {
+                self.error_handler.handle(Error::Io(err));
+            }
+        }
+
+        let _ = self.drained.send(());
+    }
+}
+
+pub struct UdpTransportBuilder {
+    transport_protocol: Option<TransportProtocol>,
+    service_name: Option<String>,
+    max_packet_size: usize,
+    max_queued_packets: usize,
+    span_buffer_size: usize,
+    tags: Option<Vec<Tag>>,
+    error_handler: Option<Arc<dyn ErrorHandler>>,
+}
+
+impl Default for UdpTransportBuilder {
+    fn default() -> Self {
+        Self {
+            transport_protocol: None,
+            service_name: None,
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            max_queued_packets: DEFAULT_MAX_QUEUED_PACKETS,
+            span_buffer_size: 1000,
+            tags: None,
+            error_handler: None,
+        }
+    }
+}
+
+impl UdpTransportBuilder {
+    pub fn transport_protocol(mut self, protocol: TransportProtocol) -> Self {
+        self.transport_protocol = Some(protocol);
+        self
+    }
+
+    pub fn process_service_name<N>(mut self, service_name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        self.service_name = Some(service_name.into());
+        self
+    }
+
+    pub fn process_tags(mut self, tags: Vec<Tag>) -> Self {
+        self.tags = Some(tags);
+        self
+    }
+
+    /// Sets the size limit of a single datagram, spans are batched so that
+    /// no packet exceeds it.
+    pub fn max_packet_size(mut self, max_packet_size: usize) -> Self {
+        self.max_packet_size = max_packet_size;
+        self
+    }
+
+    /// Caps the packets waiting to be sent, further packets are dropped
+    /// until the socket catches up.
+    pub fn max_queued_packets(mut self, max_queued_packets: usize) -> Self {
+        self.max_queued_packets = max_queued_packets;
+        self
+    }
+
+    pub fn span_buffer_size(mut self, buffer_size: usize) -> Self {
+        self.span_buffer_size = buffer_size;
+        self
+    }
+
+    /// Sets the handler of encoding and sending failures, which are printed
+    /// to stderr by default.
+    pub fn error_handler<H>(mut self, error_handler: H) -> Self
+    where
+        H: ErrorHandler + 'static,
+    {
+        self.error_handler = Some(Arc::new(error_handler));
+        self
+    }
+
+    pub(crate) fn shared_error_handler(mut self, error_handler: Arc<dyn ErrorHandler>) -> Self {
+        self.error_handler = Some(error_handler);
+        self
+    }
+
+    pub fn build_and_serve(
+        self,
+        agent_addr: SocketAddr,
+    ) -> crate::Result<(UdpTransport, impl Future<Output = ()>)> {
+        if self.span_buffer_size < 2 {
+            return Err(Error::InvalidConfig(format!(
+                "span buffer size must be at least 2, received {}",
+                self.span_buffer_size
+            )));
+        }
+
+        let error_handler = self
+            .error_handler
+            .unwrap_or_else(|| Arc::new(LoggingErrorHandler::default()));
+        let process = build_process(self.service_name, self.tags);
+
+        let encoder = {
+            let buffer_size = self.max_packet_size;
+            let protocol = self
+                .transport_protocol
+                .unwrap_or(TransportProtocol::ThriftBinary);
+
+            match protocol {
+                TransportProtocol::ThriftBinary => ThriftEncoder::binary_protocol(buffer_size),
+                TransportProtocol::ThriftCompact => ThriftEncoder::compact_protocol(buffer_size),
+            }
+        };
+
+        if self.max_queued_packets == 0 {
+            return Err(Error::InvalidConfig(
+                "max queued packets must not be zero".to_owned(),
+            ));
+        }
+        let to_send = BoundedQueue::new(self.max_queued_packets, DropPolicy::Newest);
+
+        let (drained_tx, drained_rx) = oneshot::channel();
+        let sender = UdpSender::new(
+            agent_addr,
+            to_send.receiver(),
+            drained_tx,
+            error_handler.clone(),
+        )?;
+
+        let transport = UdpTransport::new(
+            process,
+            to_send,
+            encoder,
+            self.span_buffer_size,
+            self.max_packet_size,
+            error_handler,
+            drained_rx,
+        );
+
+        Ok((transport, sender.run()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{collecting_error_handler, finished_span};
+    use crate::{SpanState, TraceId};
+
+    fn transport(max_packet_size: usize) -> (UdpTransport, Arc<std::sync::Mutex<Vec<Error>>>) {
+        let (error_handler, errors) = collecting_error_handler();
+        let (transport, _) = UdpTransport::builder()
+            .max_packet_size(max_packet_size)
+            .error_handler(error_handler)
+            .build_and_serve(SocketAddr::from(([127, 0, 0, 1], 6831)))
+            .unwrap();
+        (transport, errors)
+    }
+
+    fn sampled_span(tags: Vec<Tag>) -> Span {
+        let state = SpanState::new(TraceId { high: 0, low: 1 }, 2, true);
+        finished_span("op", state, tags)
+    }
+
+    #[test]
+    fn counts_spans_larger_than_a_packet() {
+        let (mut transport, errors) = transport(1000);
+
+        transport.append(sampled_span(vec![Tag::new("payload", "x".repeat(5000))]));
+        transport.append(sampled_span(vec![Tag::new("payload", "x".repeat(900))]));
+        transport.flush();
+
+        assert_eq!(transport.too_large_spans(), 2);
+        assert_eq!(transport.to_send.len(), 0);
+        assert!(errors.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn splits_batches_into_packets() {
+        let (mut transport, errors) = transport(1000);
+
+        for _ in 0..4 {
+            transport.append(sampled_span(vec![Tag::new("payload", "x".repeat(300))]));
+        }
+        transport.flush();
+
+        assert_eq!(transport.too_large_spans(), 0);
+        assert!(transport.to_send.len() >= 2);
+        assert!(errors.lock().unwrap().is_empty());
+    }
+}
@@ -1,2 +1,5 @@
-pub use opentracing_rs_core::{Span, SpanBuilder, SpanContext, Tag, Tracer};
+pub use opentracing_rs_core::{
+    Format, Log, PropagationError, Span, SpanBuilder, SpanContext, Tag, TextMapReader,
+    TextMapWriter, Tracer,
+};
 pub use opentracing_rs_jaeger as jaeger;